@@ -0,0 +1,317 @@
+use std::{
+    fmt::Write as _,
+    fs, io,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::SystemTime,
+};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_renet::renet::RenetServer;
+
+use crate::{server::ServerSender, settings::ServerSettings, ServerChannel, ServerMessages};
+
+// how often the ban list file is checked for changes, in seconds
+pub const BAN_LIST_RELOAD_INTERVAL: f32 = 2.0;
+// time for the refusal reason to reach the client before it is disconnected
+pub const REFUSAL_GRACE: f32 = 0.5;
+
+/// A client id or IP address, as typed in the admin console or the ban list file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientTarget {
+    Id(u64),
+    Ip(IpAddr),
+}
+
+impl FromStr for ClientTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        if let Ok(ip) = value.parse() {
+            return Ok(ClientTarget::Ip(ip));
+        }
+        value
+            .parse()
+            .map(ClientTarget::Id)
+            .map_err(|_| format!("{} is neither a client id nor an IP address", value))
+    }
+}
+
+/// Clients refused on connect, checked by `server_update_system`.
+///
+/// When loaded from a file every change is written back to it, and edits made to
+/// the file by hand are picked up while the server runs.
+#[derive(Debug, Resource)]
+pub struct BanList {
+    ids: HashSet<u64>,
+    ips: HashSet<IpAddr>,
+    path: Option<PathBuf>,
+    modified: Option<SystemTime>,
+    reload_timer: Timer,
+}
+
+impl Default for BanList {
+    fn default() -> Self {
+        Self {
+            ids: HashSet::default(),
+            ips: HashSet::default(),
+            path: None,
+            modified: None,
+            reload_timer: Timer::from_seconds(BAN_LIST_RELOAD_INTERVAL, TimerMode::Repeating),
+        }
+    }
+}
+
+impl BanList {
+    /// A missing file is an empty ban list, it gets created on the first ban.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut bans = Self {
+            path: Some(path.to_path_buf()),
+            ..Default::default()
+        };
+        if path.exists() {
+            bans.read()?;
+        }
+        Ok(bans)
+    }
+
+    pub fn from_settings(settings: &ServerSettings) -> Self {
+        match &settings.ban_list {
+            Some(path) => Self::load(path).unwrap_or_else(|e| {
+                error!("Failed to read ban list {}: {}", path.display(), e);
+                Self {
+                    path: Some(path.clone()),
+                    ..Default::default()
+                }
+            }),
+            None => Self::default(),
+        }
+    }
+
+    pub fn ban(&mut self, target: ClientTarget) {
+        match target {
+            ClientTarget::Id(id) => self.ids.insert(id),
+            ClientTarget::Ip(ip) => self.ips.insert(ip),
+        };
+        self.save();
+    }
+
+    pub fn unban(&mut self, target: ClientTarget) -> bool {
+        let removed = match target {
+            ClientTarget::Id(id) => self.ids.remove(&id),
+            ClientTarget::Ip(ip) => self.ips.remove(&ip),
+        };
+        if removed {
+            self.save();
+        }
+        removed
+    }
+
+    pub fn is_banned(&self, id: u64, ip: Option<IpAddr>) -> bool {
+        self.ids.contains(&id) || ip.map_or(false, |ip| self.ips.contains(&ip))
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len() + self.ips.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // one client id or IP address per line, `#` starts a comment
+    fn read(&mut self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = fs::read_to_string(path)?;
+        self.modified = self.modified_on_disk();
+        self.ids.clear();
+        self.ips.clear();
+        for line in contents.lines() {
+            let entry = line.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            match entry.parse() {
+                Ok(ClientTarget::Id(id)) => {
+                    self.ids.insert(id);
+                }
+                Ok(ClientTarget::Ip(ip)) => {
+                    self.ips.insert(ip);
+                }
+                Err(e) => warn!("Skipping ban list entry: {}", e),
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&mut self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+        let mut ids: Vec<u64> = self.ids.iter().copied().collect();
+        ids.sort_unstable();
+        let mut ips: Vec<IpAddr> = self.ips.iter().copied().collect();
+        ips.sort_unstable();
+
+        let mut contents = String::from("# one client id or IP address per line\n");
+        for id in ids {
+            let _ = writeln!(contents, "{}", id);
+        }
+        for ip in ips {
+            let _ = writeln!(contents, "{}", ip);
+        }
+        if let Err(e) = fs::write(path, contents) {
+            error!("Failed to write ban list {}: {}", path.display(), e);
+            return;
+        }
+        self.modified = self.modified_on_disk();
+    }
+
+    fn modified_on_disk(&self) -> Option<SystemTime> {
+        let path = self.path.as_ref()?;
+        fs::metadata(path).and_then(|m| m.modified()).ok()
+    }
+
+    fn changed_on_disk(&self) -> bool {
+        self.path.is_some() && self.modified_on_disk() != self.modified
+    }
+
+    /// Reads the file again if it changed since it was last read or written.
+    pub fn reload_if_changed(&mut self) -> io::Result<bool> {
+        if !self.changed_on_disk() {
+            return Ok(false);
+        }
+        if let Err(e) = self.read() {
+            // don't retry until the file changes again
+            self.modified = self.modified_on_disk();
+            return Err(e);
+        }
+        Ok(true)
+    }
+}
+
+/// Clients that were told why they can't play and are about to be disconnected.
+#[derive(Debug, Default, Resource)]
+pub struct Refusals {
+    pending: HashMap<u64, Timer>,
+    // disconnected by us, until `server_update_system` sees them go
+    disconnected: HashSet<u64>,
+}
+
+impl Refusals {
+    /// Sends `reason` to the client and disconnects it once the message had time to arrive.
+    pub fn refuse(&mut self, server: &mut ServerSender, client_id: u64, reason: &str) {
+        if self.is_refused(client_id) {
+            return;
+        }
+        info!(client = client_id, "Refusing connection: {}", reason);
+        let message = bincode::serialize(&ServerMessages::ConnectionRefused {
+            reason: reason.to_string(),
+        })
+        .unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+        self.pending.insert(
+            client_id,
+            Timer::from_seconds(REFUSAL_GRACE, TimerMode::Once),
+        );
+    }
+
+    pub fn is_refused(&self, client_id: u64) -> bool {
+        self.pending.contains_key(&client_id)
+    }
+
+    /// Whether the client was disconnected for being refused, forgetting it afterwards.
+    pub fn take_disconnected(&mut self, client_id: u64) -> bool {
+        self.disconnected.remove(&client_id)
+    }
+}
+
+/// Decides whether a freshly connected client may join, and why not.
+///
+/// `others` holds the address of every other client that is staying connected.
+pub fn check_connection(
+    bans: &BanList,
+    settings: &ServerSettings,
+    client_id: u64,
+    ip: Option<IpAddr>,
+    others: &[IpAddr],
+) -> Result<(), &'static str> {
+    if bans.is_banned(client_id, ip) {
+        return Err("You are banned from this server.");
+    }
+
+    if let (Some(ip), Some(limit)) = (ip, settings.max_connections_per_ip) {
+        if others.iter().filter(|other| **other == ip).count() >= limit {
+            return Err("Too many connections from your address.");
+        }
+    }
+    Ok(())
+}
+
+/// Addresses of the connected clients other than `client_id`, leaving out the refused ones.
+pub fn other_addresses(server: &RenetServer, refusals: &Refusals, client_id: u64) -> Vec<IpAddr> {
+    server
+        .clients_id()
+        .into_iter()
+        .filter(|other| *other != client_id && !refusals.is_refused(*other))
+        .filter_map(|other| server.client_addr(other).map(|addr| addr.ip()))
+        .collect()
+}
+
+pub fn disconnect_refused(
+    time: Res<Time>,
+    mut refusals: ResMut<Refusals>,
+    mut server: ResMut<RenetServer>,
+) {
+    let connected = server.clients_id();
+    let Refusals {
+        pending,
+        disconnected,
+    } = &mut *refusals;
+    pending.retain(|client_id, timer| {
+        if !connected.contains(client_id) {
+            return false;
+        }
+        if timer.tick(time.delta()).finished() {
+            server.disconnect(*client_id);
+            disconnected.insert(*client_id);
+            return false;
+        }
+        true
+    });
+}
+
+/// Picks up hand edits to the ban list file and refuses anyone newly banned.
+pub fn reload_ban_list(
+    time: Res<Time>,
+    mut bans: ResMut<BanList>,
+    mut refusals: ResMut<Refusals>,
+    mut server: ServerSender,
+) {
+    if !bans.reload_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    match bans.reload_if_changed() {
+        Ok(true) => info!("Reloaded ban list, {} entries.", bans.len()),
+        Ok(false) => return,
+        Err(e) => {
+            error!("Failed to reload ban list: {}", e);
+            return;
+        }
+    }
+
+    for client_id in server.clients_id() {
+        let ip = server.client_addr(client_id).map(|addr| addr.ip());
+        if bans.is_banned(client_id, ip) {
+            refusals.refuse(&mut server, client_id, "You are banned from this server.");
+        }
+    }
+}
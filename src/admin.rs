@@ -0,0 +1,351 @@
+use std::{
+    io::{self, BufRead},
+    str::FromStr,
+    sync::{
+        mpsc::{channel, Receiver},
+        Mutex,
+    },
+    thread,
+};
+
+use bevy::prelude::*;
+
+use crate::{
+    access::{BanList, ClientTarget, Refusals},
+    arena::Arena,
+    cells::{spawn_food, Cell, NpcCell},
+    chat::sanitize_chat,
+    rng::ServerRng,
+    server::ServerSender,
+    spawn::SpawnPlacement,
+    Player, ServerChannel, ServerLobby, ServerMessages,
+};
+
+pub const ADMIN_HELP: &str = "\
+list                         show id, name, room, mass and ping of everyone
+kick <id|ip>                 disconnect a client
+ban <id|ip>                  disconnect a client and refuse it from now on
+unban <id|ip>                lift a ban
+food spawn <count> [room]    spawn food right away, in every room by default
+food clear [room]            remove all food
+spawn-rate <seconds> [room]  time between two food spawns, in every room by default
+max-food <count> [room]      food cap, in every room by default
+say <text>                   announce to every client";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdminCommand {
+    Help,
+    List,
+    Kick(ClientTarget),
+    Ban(ClientTarget),
+    Unban(ClientTarget),
+    SpawnFood { count: usize, room: Option<String> },
+    ClearFood { room: Option<String> },
+    SpawnRate { seconds: f32, room: Option<String> },
+    MaxFood { count: usize, room: Option<String> },
+    Say(String),
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let mut words = line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return Err("empty command".to_string()),
+        };
+        let command = match command {
+            "help" => AdminCommand::Help,
+            "list" => AdminCommand::List,
+            "kick" => AdminCommand::Kick(argument(words.next(), "kick <id|ip>")?),
+            "ban" => AdminCommand::Ban(argument(words.next(), "ban <id|ip>")?),
+            "unban" => AdminCommand::Unban(argument(words.next(), "unban <id|ip>")?),
+            "food" => match words.next() {
+                Some("spawn") => AdminCommand::SpawnFood {
+                    count: argument(words.next(), "food spawn <count> [room]")?,
+                    room: words.next().map(str::to_string),
+                },
+                Some("clear") => AdminCommand::ClearFood {
+                    room: words.next().map(str::to_string),
+                },
+                _ => return Err("usage: food spawn <count> [room] | food clear [room]".into()),
+            },
+            "spawn-rate" => {
+                let seconds: f32 = argument(words.next(), "spawn-rate <seconds> [room]")?;
+                if !seconds.is_finite() || seconds <= 0.0 {
+                    return Err("the spawn rate has to be a positive number of seconds".into());
+                }
+                AdminCommand::SpawnRate {
+                    seconds,
+                    room: words.next().map(str::to_string),
+                }
+            }
+            "max-food" => AdminCommand::MaxFood {
+                count: argument(words.next(), "max-food <count> [room]")?,
+                room: words.next().map(str::to_string),
+            },
+            "say" => {
+                let text = sanitize_chat(&line["say".len()..]);
+                if text.is_empty() {
+                    return Err("usage: say <text>".into());
+                }
+                AdminCommand::Say(text)
+            }
+            _ => return Err(format!("unknown command {}, try help", command)),
+        };
+        Ok(command)
+    }
+}
+
+fn argument<T: FromStr>(value: Option<&str>, usage: &str) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("usage: {}", usage))
+}
+
+/// Lines typed into the server's terminal, read on a background thread so the
+/// frame never blocks on stdin.
+#[derive(Resource)]
+pub struct AdminConsole {
+    lines: Mutex<Receiver<String>>,
+}
+
+impl AdminConsole {
+    pub fn stdin() -> Self {
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let stdin = io::stdin();
+            for line in stdin.lock().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self {
+            lines: Mutex::new(receiver),
+        }
+    }
+
+    fn pending(&self) -> Vec<String> {
+        self.lines.lock().unwrap().try_iter().collect()
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn admin_console_system(
+    console: Res<AdminConsole>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut server: ServerSender,
+    lobby: Res<ServerLobby>,
+    mut bans: ResMut<BanList>,
+    mut refusals: ResMut<Refusals>,
+    mut rng: ResMut<ServerRng>,
+    spawn_placement: Res<SpawnPlacement>,
+    players: Query<&Cell, With<Player>>,
+    cells: Query<(Entity, &Transform, &Cell, &Arena, Option<&NpcCell>)>,
+) {
+    for line in console.pending() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let command = match line.parse::<AdminCommand>() {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            }
+        };
+
+        match command {
+            AdminCommand::Help => println!("{}", ADMIN_HELP),
+            AdminCommand::List => {
+                let mut ids: Vec<u64> = lobby.names.keys().copied().collect();
+                ids.sort_unstable();
+                println!(
+                    "{:>20}  {:<16}  {:<10}  {:>8}  {:>8}",
+                    "id", "name", "room", "mass", "ping"
+                );
+                for id in ids {
+                    let mass = match lobby.players.get(&id) {
+                        Some(entity) => players
+                            .get(*entity)
+                            .map(|cell| format!("{:.1}", cell.size))
+                            .unwrap_or_default(),
+                        None if lobby.spectators.contains(&id) => "spectator".to_string(),
+                        None => "eaten".to_string(),
+                    };
+                    // bots have no connection
+                    let ping = match server.network_info(id) {
+                        Some(network_info) => format!("{:.0}ms", network_info.rtt),
+                        None => "bot".to_string(),
+                    };
+                    println!(
+                        "{:>20}  {:<16}  {:<10}  {:>8}  {:>8}",
+                        id,
+                        lobby.names[&id],
+                        server.arenas().settings(server.arenas().arena_of(id)).code,
+                        mass,
+                        ping
+                    );
+                }
+            }
+            AdminCommand::Kick(target) => {
+                let kicked = matching_clients(&server, target);
+                if kicked.is_empty() {
+                    println!("No client matches {:?}.", target);
+                }
+                for id in kicked {
+                    refusals.refuse(&mut server, id, "You were kicked from this server.");
+                }
+            }
+            AdminCommand::Ban(target) => {
+                bans.ban(target);
+                for id in matching_clients(&server, target) {
+                    // ids are picked by the client, so the address is banned along with it
+                    if let Some(addr) = server.client_addr(id) {
+                        bans.ban(ClientTarget::Ip(addr.ip()));
+                    }
+                    refusals.refuse(&mut server, id, "You are banned from this server.");
+                }
+                println!("Banned {:?}.", target);
+            }
+            AdminCommand::Unban(target) => {
+                if bans.unban(target) {
+                    println!("Unbanned {:?}.", target);
+                } else {
+                    println!("{:?} is not banned.", target);
+                }
+            }
+            AdminCommand::SpawnFood { count, room } => {
+                let arenas = match target_arenas(&server, room.as_deref()) {
+                    Ok(arenas) => arenas,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                for arena in arenas {
+                    let mut occupied: Vec<(Vec3, f32)> = cells
+                        .iter()
+                        .filter(|(_, _, _, cell_arena, _)| **cell_arena == arena)
+                        .map(|(_, transform, cell, _, _)| (transform.translation, cell.size))
+                        .collect();
+                    let mut spawned = 0;
+                    for _ in 0..count {
+                        let food = spawn_food(
+                            &mut commands,
+                            &mut meshes,
+                            &mut materials,
+                            &mut server,
+                            &mut rng,
+                            &spawn_placement,
+                            &occupied,
+                            arena,
+                        );
+                        match food {
+                            Some((_, translation, size)) => {
+                                occupied.push((translation, size));
+                                spawned += 1;
+                            }
+                            None => break,
+                        }
+                    }
+                    println!(
+                        "Spawned {} food in {}.",
+                        spawned,
+                        server.arenas().settings(arena).code
+                    );
+                }
+            }
+            AdminCommand::ClearFood { room } => {
+                let arenas = match target_arenas(&server, room.as_deref()) {
+                    Ok(arenas) => arenas,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                let mut cleared = 0;
+                for (entity, _, _, arena, npc) in cells.iter() {
+                    if npc.is_some() && arenas.contains(arena) {
+                        commands.entity(entity).despawn_recursive();
+                        cleared += 1;
+                    }
+                }
+                println!("Cleared {} food.", cleared);
+            }
+            AdminCommand::SpawnRate { seconds, room } => {
+                let arenas = match target_arenas(&server, room.as_deref()) {
+                    Ok(arenas) => arenas,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                for arena in arenas {
+                    server.arenas_mut().set_spawn_rate(arena, seconds);
+                    println!(
+                        "Food in {} spawns every {}s.",
+                        server.arenas().settings(arena).code,
+                        seconds
+                    );
+                }
+            }
+            AdminCommand::MaxFood { count, room } => {
+                let arenas = match target_arenas(&server, room.as_deref()) {
+                    Ok(arenas) => arenas,
+                    Err(e) => {
+                        println!("{}", e);
+                        continue;
+                    }
+                };
+                for arena in arenas {
+                    let settings = server.arenas_mut().settings_mut(arena);
+                    settings.max_food = count;
+                    println!("Food in {} is capped at {}.", settings.code, count);
+                }
+            }
+            AdminCommand::Say(text) => {
+                println!("Announced: {}", text);
+                let message = bincode::serialize(&ServerMessages::Chat {
+                    id: None,
+                    name: "Server".to_string(),
+                    text,
+                })
+                .unwrap();
+                server.broadcast_message(ServerChannel::ServerMessages, message);
+            }
+        }
+    }
+}
+
+fn matching_clients(server: &ServerSender, target: ClientTarget) -> Vec<u64> {
+    server
+        .clients_id()
+        .into_iter()
+        .filter(|id| match target {
+            ClientTarget::Id(target) => *id == target,
+            ClientTarget::Ip(ip) => server.client_addr(*id).map(|addr| addr.ip()) == Some(ip),
+        })
+        .collect()
+}
+
+// every arena when no room is given
+fn target_arenas(server: &ServerSender, room: Option<&str>) -> Result<Vec<Arena>, String> {
+    match room {
+        Some(code) => server
+            .arenas()
+            .find(code)
+            .map(|arena| vec![arena])
+            .ok_or_else(|| format!("there is no room {}", code)),
+        None => Ok(server.arenas().iter().map(|(arena, _)| arena).collect()),
+    }
+}
@@ -0,0 +1,211 @@
+use std::time::Duration;
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_rapier3d::prelude::{CollisionGroups, Group};
+
+use crate::{cells::MAX_SPHERES, settings::ServerSettings};
+
+// every arena gets its own rapier collision group, and there are 32 of them
+pub const MAX_ARENAS: usize = 32;
+pub const DEFAULT_ROOM: &str = "main";
+// seconds between two food spawns
+pub const DEFAULT_SPAWN_RATE: f32 = 0.2;
+
+/// Which arena a server-side cell lives in.
+///
+/// bevy_rapier only has the one physics world, so all arenas share it and one
+/// coordinate space. They are kept apart by collision groups, and messages
+/// about a cell only go to the clients of its arena.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Component)]
+pub struct Arena(pub u32);
+
+impl Arena {
+    /// Cells only collide with cells in the same arena.
+    pub fn collision_groups(self) -> CollisionGroups {
+        let group = Group::from_bits_truncate(1 << (self.0 as usize % MAX_ARENAS));
+        CollisionGroups::new(group, group)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArenaSettings {
+    // clients join by passing this in `ConnectionData::room`
+    pub code: String,
+    pub max_food: usize,
+    // seconds between two food spawns
+    pub spawn_rate: f32,
+    // round length in seconds, endless when not set
+    pub round_duration: Option<f32>,
+}
+
+impl ArenaSettings {
+    pub fn new(code: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            max_food: MAX_SPHERES,
+            spawn_rate: DEFAULT_SPAWN_RATE,
+            round_duration: None,
+        }
+    }
+}
+
+/// The rooms hosted by this server and who is in each of them.
+///
+/// Owned by `ServerSender`, which uses the membership to route messages.
+///
+/// Rooms don't get a physics space of their own: bevy_rapier runs a single world,
+/// so they are separated by the collision groups of `Arena`. Rapier has 32 groups,
+/// which is where `MAX_ARENAS` comes from and why `new` refuses more rooms.
+#[derive(Debug, Resource)]
+pub struct Arenas {
+    rooms: Vec<ArenaSettings>,
+    // one per room, at its spawn rate
+    food_timers: Vec<Timer>,
+    // connected clients and bots, by id
+    members: HashMap<u64, Arena>,
+    // needed to route `DespawnEntity` once the cell is already gone
+    entities: HashMap<Entity, Arena>,
+}
+
+impl Default for Arenas {
+    fn default() -> Self {
+        Self::new(Vec::new()).unwrap()
+    }
+}
+
+impl Arenas {
+    /// A single default room when `rooms` is empty, an error when there are too many.
+    pub fn new(mut rooms: Vec<ArenaSettings>) -> Result<Self, String> {
+        if rooms.len() > MAX_ARENAS {
+            return Err(format!(
+                "{} rooms were given, a server hosts at most {}",
+                rooms.len(),
+                MAX_ARENAS
+            ));
+        }
+        if rooms.is_empty() {
+            rooms.push(ArenaSettings::new(DEFAULT_ROOM));
+        }
+        let food_timers = rooms
+            .iter()
+            .map(|room| Timer::from_seconds(room.spawn_rate, TimerMode::Repeating))
+            .collect();
+        Ok(Self {
+            rooms,
+            food_timers,
+            members: HashMap::default(),
+            entities: HashMap::default(),
+        })
+    }
+
+    /// Rooms without a round length of their own use `--round`.
+    pub fn from_settings(settings: &ServerSettings) -> Result<Self, String> {
+        Self::new(
+            settings
+                .rooms
+                .iter()
+                .cloned()
+                .map(|mut room| {
+                    room.round_duration = room.round_duration.or(settings.round_duration);
+                    room
+                })
+                .collect(),
+        )
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Arena, &ArenaSettings)> {
+        self.rooms
+            .iter()
+            .enumerate()
+            .map(|(index, room)| (Arena(index as u32), room))
+    }
+
+    pub fn settings(&self, arena: Arena) -> &ArenaSettings {
+        &self.rooms[arena.0 as usize % self.rooms.len()]
+    }
+
+    pub fn settings_mut(&mut self, arena: Arena) -> &mut ArenaSettings {
+        let index = arena.0 as usize % self.rooms.len();
+        &mut self.rooms[index]
+    }
+
+    pub fn set_spawn_rate(&mut self, arena: Arena, seconds: f32) {
+        let index = arena.0 as usize % self.rooms.len();
+        self.rooms[index].spawn_rate = seconds;
+        self.food_timers[index].set_duration(Duration::from_secs_f32(seconds));
+    }
+
+    /// Ticks every room's food timer, returns the arenas due for a spawn.
+    pub fn tick_food_timers(&mut self, delta: Duration) -> Vec<Arena> {
+        self.food_timers
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, timer)| {
+                timer
+                    .tick(delta)
+                    .just_finished()
+                    .then_some(Arena(index as u32))
+            })
+            .collect()
+    }
+
+    pub fn find(&self, code: &str) -> Option<Arena> {
+        self.rooms
+            .iter()
+            .position(|room| room.code == code.trim())
+            .map(|index| Arena(index as u32))
+    }
+
+    /// Puts `id` in the room with a matching code, or the emptiest room otherwise.
+    pub fn join(&mut self, id: u64, code: &str) -> Arena {
+        let arena = match self.find(code) {
+            Some(arena) => arena,
+            None => {
+                let mut population = vec![0; self.rooms.len()];
+                for arena in self.members.values() {
+                    population[arena.0 as usize] += 1;
+                }
+                let emptiest = (0..self.rooms.len())
+                    .min_by_key(|index| population[*index])
+                    .unwrap_or(0);
+                Arena(emptiest as u32)
+            }
+        };
+        self.members.insert(id, arena);
+        arena
+    }
+
+    pub fn leave(&mut self, id: u64) {
+        self.members.remove(&id);
+    }
+
+    /// Everyone not assigned yet is treated as being in the first arena.
+    pub fn arena_of(&self, id: u64) -> Arena {
+        self.members.get(&id).copied().unwrap_or_default()
+    }
+
+    pub fn is_assigned(&self, id: u64) -> bool {
+        self.members.contains_key(&id)
+    }
+
+    pub fn is_member(&self, id: u64, arena: Arena) -> bool {
+        self.arena_of(id) == arena
+    }
+
+    pub fn track_entity(&mut self, entity: Entity, arena: Arena) {
+        self.entities.insert(entity, arena);
+    }
+
+    pub fn forget_entity(&mut self, entity: Entity) -> Option<Arena> {
+        self.entities.remove(&entity)
+    }
+}
+
+pub fn track_arena_entities(
+    mut arenas: ResMut<Arenas>,
+    added: Query<(Entity, &Arena), Added<Arena>>,
+) {
+    for (entity, arena) in added.iter() {
+        arenas.track_entity(entity, *arena);
+    }
+}
@@ -1,78 +1,120 @@
-use std::{collections::HashMap, net::UdpSocket, time::SystemTime};
+use std::{
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
 
-use bevy::{app::AppExit, prelude::*, window::exit_on_all_closed};
+use bevy::{app::AppExit, log::LogPlugin, prelude::*, window::exit_on_all_closed};
 use bevy_egui::EguiPlugin;
 use bevy_inspector_egui::WorldInspectorPlugin;
-use bevy_renet::{
-    renet::{ClientAuthentication, RenetClient},
-    run_if_client_connected, RenetClientPlugin,
-};
+use bevy_renet::{renet::RenetClient, run_if_client_connected, RenetClientPlugin};
 use simula_action::ActionPlugin;
 use simula_camera::orbitcam::*;
 // use simula_video::rt;
 use smooth_bevy_cameras::LookTransformPlugin;
 
 use bevy_rapier3d::{
-    prelude::{ActiveEvents, Collider, NoUserData, RapierPhysicsPlugin, Velocity},
+    prelude::{NoUserData, RapierPhysicsPlugin},
     render::RapierDebugRenderPlugin,
 };
 
 use simula_viz::{grid::GridPlugin, lines::LinesPlugin};
 
-use cagario::physics::*;
+use cagario::client::*;
 use cagario::player::*;
 use cagario::{
-    camera_follow, cells::*, client_connection_config, setup_camera, spawn_grid_lines, spawn_scene,
-    ClientChannel, ControlledPlayer, GameState, NetworkedEntities, PlayerCommand, PlayerInput,
-    ServerChannel, ServerMessages, PROTOCOL_ID,
+    camera_follow, cells::*, chat::*, setup_camera, spawn_grid_lines, spawn_scene, ClientChannel,
+    GameState, PlayerCommand, PlayerInput,
+};
+use cagario::{
+    game_over::*,
+    hud::HudPlugin,
+    kill_feed::KillFeedPlugin,
+    leaderboard::*,
+    logging::{init_logging, LogSettings},
+    main_menu::*,
+    net_debug::{client_network_debug, ClientNetworkDebug},
+    profile::{load_profile_key, ClientProfile, DEFAULT_PROFILE_KEY_FILE},
+    replay::*,
+    round::RoundPlugin,
+    session::{client_reconnect, reconnecting_overlay, ClientSession},
+    spectator::SpectatorPlugin,
+    ConnectionData, WINDOW_HEIGHT, WINDOW_WIDTH,
 };
-use cagario::{main_menu::*, WINDOW_HEIGHT, WINDOW_WIDTH};
-
-#[derive(Default, Resource)]
-struct NetworkMapping(HashMap<Entity, Entity>);
-
-#[derive(Debug)]
-struct PlayerInfo {
-    client_entity: Entity,
-    server_entity: Entity,
-}
 
-#[derive(Debug, Default, Resource)]
-pub struct ClientLobby {
-    players: HashMap<u64, PlayerInfo>,
-}
-fn new_renet_client() -> RenetClient {
+// the id is kept for the whole run, reconnecting with it resumes the session
+fn new_client_session(connection_data: &ConnectionData) -> ClientSession {
     let server_addr = "127.0.0.1:5000".parse().unwrap();
-    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
-    let connection_config = client_connection_config();
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
     let client_id = current_time.as_millis() as u64;
-    let authentication = ClientAuthentication::Unsecure {
-        client_id,
-        protocol_id: PROTOCOL_ID,
-        server_addr,
-        user_data: None,
-    };
+    ClientSession::new(server_addr, client_id, connection_data.clone())
+}
 
-    RenetClient::new(current_time, socket, connection_config, authentication).unwrap()
+// usage: client [--name <name>] [--room <code>] [--spectate] [--replay <file>]
+//               [--profile <key file>] [--log <filter>] [--log-json]
+#[derive(Debug, Default)]
+struct ClientArgs {
+    connection_data: ConnectionData,
+    replay: Option<PathBuf>,
+    // DEFAULT_PROFILE_KEY_FILE when not set
+    profile: Option<PathBuf>,
+    log: LogSettings,
+    // logged once logging is set up
+    warnings: Vec<String>,
+}
+
+fn client_args() -> ClientArgs {
+    let mut client_args = ClientArgs::default();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--name" => {
+                if let Some(name) = args.next() {
+                    client_args.connection_data.name = name;
+                }
+            }
+            "--room" => {
+                if let Some(room) = args.next() {
+                    client_args.connection_data.room = room;
+                }
+            }
+            "--spectate" => client_args.connection_data.spectator = true,
+            "--replay" => client_args.replay = args.next().map(PathBuf::from),
+            "--profile" => client_args.profile = args.next().map(PathBuf::from),
+            _ if client_args.log.parse_arg(&arg, &mut args) => {}
+            _ => client_args
+                .warnings
+                .push(format!("Ignoring unknown argument {}.", arg)),
+        }
+    }
+    client_args
 }
 
 fn main() {
+    let args = client_args();
+    init_logging(&args.log);
+    for warning in args.warnings.iter() {
+        warn!("{}", warning);
+    }
     let mut app = App::new();
 
     app.insert_resource(ClearColor(Color::rgb(0.2, 0.2, 0.2)))
-        .add_plugins(DefaultPlugins.set(WindowPlugin {
-            window: WindowDescriptor {
-                width: WINDOW_WIDTH,
-                height: WINDOW_HEIGHT,
-                title: "Cagario".to_string(),
-                resizable: false,
-                ..default()
-            },
-            ..default()
-        }))
+        .add_plugins(
+            DefaultPlugins
+                .set(WindowPlugin {
+                    window: WindowDescriptor {
+                        width: WINDOW_WIDTH,
+                        height: WINDOW_HEIGHT,
+                        title: "Cagario".to_string(),
+                        resizable: false,
+                        ..default()
+                    },
+                    ..default()
+                })
+                .disable::<LogPlugin>(),
+        )
         .insert_resource(PlayerInput::default())
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         .add_plugin(RapierDebugRenderPlugin::default())
@@ -80,194 +122,126 @@ fn main() {
         .add_plugin(ActionPlugin)
         .add_plugin(OrbitCameraPlugin)
         // .add_plugin(FlyCameraPlugin)
-        .add_plugin(WorldInspectorPlugin::new())
-        .add_state(GameState::MainMenu);
+        .add_plugin(WorldInspectorPlugin::new());
 
     app.add_event::<PlayerCommand>();
     app.register_type::<Cell>();
     app.register_type::<PlayerInput>();
     app.insert_resource(ClientLobby::default());
     app.insert_resource(PlayerInput::default());
-    app.insert_resource(new_renet_client());
     app.insert_resource(NetworkMapping::default());
+    app.init_resource::<ClientLeaderboard>();
+    app.init_resource::<ClientProfile>();
 
     // app.add_startup_system(setup_camera);
     // app.add_system(camera_follow);
 
+    match args.replay {
+        Some(path) => add_replay_viewer(&mut app, &path),
+        None => {
+            let mut connection_data = args.connection_data.clone();
+            let key_file = args
+                .profile
+                .unwrap_or_else(|| PathBuf::from(DEFAULT_PROFILE_KEY_FILE));
+            connection_data.profile = load_profile_key(&key_file);
+            add_live_client(&mut app, &connection_data);
+        }
+    }
+
     // my plugins
-    app.add_plugin(MainMenuPlugin)
+    app.add_plugin(HudPlugin)
+        .add_plugin(ChatPlugin)
+        .add_plugin(RoundPlugin)
+        .add_plugin(KillFeedPlugin)
         .add_plugin(LinesPlugin)
         .add_plugin(GridPlugin)
         .add_plugin(LookTransformPlugin)
         // .add_startup_system_to_stage(StartupStage::PreStartup, asset_loading)
-        .add_plugin(RenetClientPlugin::default())
         .add_startup_system(spawn_scene)
         .add_startup_system(spawn_grid_lines)
         .add_startup_system(setup_camera)
-        .add_system(camera_follow)
-        .add_system(client_send_input.with_run_criteria(run_if_client_connected))
-        .add_system(client_send_player_commands.with_run_criteria(run_if_client_connected))
-        .add_system(client_sync_players.with_run_criteria(run_if_client_connected))
-        .add_system(player_input)
+        .add_system_set(SystemSet::on_update(GameState::InGame).with_system(leaderboard_window))
         .add_plugin(PlayerPlugin)
         .add_system_to_stage(
             CoreStage::PostUpdate,
-            disconnect_on_exit.after(exit_on_all_closed),
+            leave_on_exit.after(exit_on_all_closed),
         )
         .add_system_set(SystemSet::on_enter(GameState::InGame).with_system(spawn_grid_lines))
         .run();
 }
 
-fn client_send_input(player_input: Res<PlayerInput>, mut client: ResMut<RenetClient>) {
-    let input_message = bincode::serialize(&*player_input).unwrap();
-    client.send_message(ClientChannel::Input, input_message);
-}
-
-fn client_send_player_commands(
-    mut player_commands: EventReader<PlayerCommand>,
-    mut client: ResMut<RenetClient>,
-) {
-    for command in player_commands.iter() {
-        let command_message = bincode::serialize(command).unwrap();
-        client.send_message(ClientChannel::Command, command_message);
+fn add_live_client(app: &mut App, connection_data: &ConnectionData) {
+    if connection_data.spectator {
+        app.add_plugin(SpectatorPlugin);
     }
+    let session = new_client_session(connection_data);
+    app.add_state(GameState::MainMenu)
+        .insert_resource(session.renet_client())
+        .insert_resource(session)
+        .add_plugin(MainMenuPlugin)
+        .add_plugin(GameOverPlugin)
+        .add_plugin(RenetClientPlugin::default())
+        .add_system(camera_follow)
+        .add_system(client_send_input.with_run_criteria(run_if_client_connected))
+        .add_system(client_send_player_commands.with_run_criteria(run_if_client_connected))
+        .add_system(client_send_chat.with_run_criteria(run_if_client_connected))
+        .add_system(client_sync_players.with_run_criteria(run_if_client_connected))
+        .init_resource::<ClientNetworkDebug>()
+        .add_system(client_network_debug)
+        .add_system(client_reconnect)
+        .add_system(reconnecting_overlay)
+        .add_system(player_input);
 }
 
-fn client_sync_players(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut client: ResMut<RenetClient>,
-    mut lobby: ResMut<ClientLobby>,
-    mut game_state: ResMut<State<GameState>>,
-    mut network_mapping: ResMut<NetworkMapping>,
-    controlled_player: Query<Entity, &ControlledPlayer>,
-) {
-    let client_id = client.client_id();
-    while let Some(message) = client.receive_message(ServerChannel::ServerMessages) {
-        let server_message = bincode::deserialize(&message).unwrap();
-        match server_message {
-            ServerMessages::PlayerCreate {
-                id,
-                translation,
-                entity,
-            } => {
-                println!("Player {} connected.", id);
-                let [x, y, z] = translation;
-                let transform = Transform::from_xyz(x, y, z);
-                let mut client_entity = commands.spawn(PbrBundle {
-                    mesh: meshes.add(Mesh::from(shape::Icosphere {
-                        radius: INITIAL_PLAYER_SIZE,
-                        subdivisions: 4,
-                    })),
-                    material: materials.add(Color::rgb(0.5, 0.5, 1.0).into()),
-                    transform,
-                    ..Default::default()
-                });
-
-                client_entity
-                    .insert(Cell {
-                        size: INITIAL_PLAYER_SIZE,
-                    })
-                    .insert(PhysicsBundle::moving_entity())
-                    .insert(Name::new("Player"))
-                    .insert(PlayerInput::default())
-                    .insert(Velocity::default())
-                    .insert(ActiveEvents::COLLISION_EVENTS)
-                    .insert(Collider::ball(INITIAL_PLAYER_SIZE / 2.0));
-
-                println!("client id: {}, id {} ", client_id, id);
-                if client_id == id {
-                    client_entity.insert(ControlledPlayer);
-                }
-
-                let player_info = PlayerInfo {
-                    server_entity: entity,
-                    client_entity: client_entity.id(),
-                };
-                lobby.players.insert(id, player_info);
-                network_mapping.0.insert(entity, client_entity.id());
-            }
-            ServerMessages::PlayerRemove { id } => {
-                println!("Player {} disconnected.", id);
-                if let Some(PlayerInfo {
-                    server_entity,
-                    client_entity,
-                }) = lobby.players.remove(&id)
-                {
-                    commands.entity(client_entity).despawn();
-                    network_mapping.0.remove(&server_entity);
-
-                    if let Ok(current_player_id) = controlled_player.get_single() {
-                        if client_entity == current_player_id {
-                            game_state.set(GameState::MainMenu).unwrap();
-                        }
-                    }
-                }
-            }
-            ServerMessages::SpawnNpcCell {
-                entity,
-                translation,
-                size,
-            } => {
-                let [x, _y, z] = translation;
-                let mut npc_entity = commands.spawn(PbrBundle {
-                    mesh: meshes.add(Mesh::from(shape::Icosphere {
-                        radius: size,
-                        subdivisions: 4,
-                    })),
-                    material: materials.add(Color::rgb(x, z, size).into()),
-                    transform: Transform::from_translation(Vec3::new(x, -size / 2.0, z)),
-                    ..Default::default()
-                });
-
-                npc_entity
-                    .insert(Cell { size })
-                    .insert(Name::new("NPC"))
-                    .insert(Collider::ball(size / 2.0))
-                    .insert(ActiveEvents::COLLISION_EVENTS)
-                    .insert(PhysicsBundle::moving_entity())
-                    .insert(NpcCell);
-                network_mapping.0.insert(entity, npc_entity.id());
-            }
-            ServerMessages::DespawnEntity { entity } => {
-                if let Some(client_entity) = network_mapping.0.remove(&entity) {
-                    commands.entity(client_entity).despawn();
-                }
-            }
-            ServerMessages::UpdateEntityCell { entity, size } => {
-                if let Some(client_entity) = network_mapping.0.get(&entity) {
-                    commands.entity(*client_entity).insert(Cell { size });
-                }
-            }
+// plays a recorded match through client_sync_players, no server needed
+fn add_replay_viewer(app: &mut App, path: &Path) {
+    let (header, frames) = match read_replay(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            error!("Failed to read replay {}: {}", path.display(), e);
+            process::exit(1);
         }
-    }
+    };
+    info!(
+        "Replaying {} ({} frames, seed {}).",
+        path.display(),
+        frames.len(),
+        header.seed
+    );
 
-    while let Some(message) = client.receive_message(ServerChannel::NetworkedEntities) {
-        let networked_entities: NetworkedEntities = bincode::deserialize(&message).unwrap();
+    app.add_state(GameState::InGame)
+        .insert_resource(ReplayPlayback::new(header, frames))
+        .init_resource::<GameOverInfo>()
+        .add_plugin(ReplayPlaybackPlugin)
+        // seeks apply in the same frame
+        .add_system(replay_controls.before(advance_replay_playback))
+        .add_system(replay_free_camera);
+}
 
-        for i in 0..networked_entities.entities.len() {
-            if let Some(entity) = network_mapping.0.get(&networked_entities.entities[i]) {
-                let translation = networked_entities.translations[i].into();
-                let scale: Vec3 = networked_entities.scalings[i].into();
-                let transform = Transform {
-                    translation,
-                    scale: Vec3::from(scale),
-                    ..Default::default()
-                };
-                commands.entity(*entity).insert(transform);
+// quitting isn't a dropped link, the server removes the cell instead of keeping it
+fn leave_on_exit(exit: EventReader<AppExit>, client: Option<ResMut<RenetClient>>) {
+    if let Some(mut client) = client {
+        if !exit.is_empty() && client.is_connected() {
+            let message = bincode::serialize(&PlayerCommand::Leave).unwrap();
+            client.send_message(ClientChannel::Command, message);
+            // this is the last frame, and the server disconnects us once it reads the command;
+            // disconnecting here too could reach it first and the command would be dropped
+            if let Err(e) = client.send_packets() {
+                warn!("Failed to say goodbye to the server: {}", e);
             }
         }
     }
 }
 
-fn disconnect_on_exit(exit: EventReader<AppExit>, mut client: ResMut<RenetClient>) {
-    if !exit.is_empty() && client.is_connected() {
-        client.disconnect();
+fn player_input(
+    keyboard_input: Res<Input<KeyCode>>,
+    chat_input: Res<ChatInput>,
+    mut player_input: ResMut<PlayerInput>,
+) {
+    if chat_input.typing {
+        *player_input = PlayerInput::default();
+        return;
     }
-}
-
-fn player_input(keyboard_input: Res<Input<KeyCode>>, mut player_input: ResMut<PlayerInput>) {
     player_input.left = keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left);
     player_input.right =
         keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right);
@@ -0,0 +1,344 @@
+use std::{
+    collections::BTreeMap,
+    net::{SocketAddr, UdpSocket},
+    process,
+    str::FromStr,
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use bevy_renet::renet::{ClientAuthentication, RenetClient};
+use rand::Rng;
+
+use cagario::{
+    client_connection_config, ClientChannel, ConnectionData, NetworkedEntities, PlayerCommand,
+    PlayerInput, ServerChannel, ServerMessages, PROTOCOL_ID,
+};
+
+const TICK: Duration = Duration::from_micros(16_667);
+// how long the random script keeps a direction before picking a new one
+const DIRECTION_CHANGE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Script {
+    Random,
+    Circle,
+}
+
+const USAGE: &str =
+    "usage: loadtest [--clients <count>] [--duration <seconds>] [--server <addr>] [--script random|circle]";
+
+#[derive(Debug)]
+struct LoadTestArgs {
+    clients: usize,
+    duration: Duration,
+    server_addr: SocketAddr,
+    script: Script,
+}
+
+impl LoadTestArgs {
+    fn from_args(args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut load_test = LoadTestArgs {
+            clients: 100,
+            duration: Duration::from_secs(30),
+            server_addr: "127.0.0.1:5000".parse().unwrap(),
+            script: Script::Random,
+        };
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--clients" => load_test.clients = argument(&arg, args.next())?,
+                "--duration" => {
+                    let seconds: f32 = argument(&arg, args.next())?;
+                    if !seconds.is_finite() || seconds < 0.0 {
+                        return Err(format!("{} takes a number of seconds", arg));
+                    }
+                    load_test.duration = Duration::from_secs_f32(seconds);
+                }
+                "--server" => load_test.server_addr = argument(&arg, args.next())?,
+                "--script" => {
+                    load_test.script = match args.next().as_deref() {
+                        Some("random") => Script::Random,
+                        Some("circle") => Script::Circle,
+                        _ => return Err(format!("{} takes random or circle", arg)),
+                    }
+                }
+                _ => return Err(format!("unknown argument {}", arg)),
+            }
+        }
+        Ok(load_test)
+    }
+}
+
+fn argument<T: FromStr>(arg: &str, value: Option<String>) -> Result<T, String> {
+    value
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| format!("{} is missing a value or it doesn't parse", arg))
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    messages: BTreeMap<&'static str, u64>,
+    message_bytes: u64,
+    sync_messages: u64,
+    sync_bytes: u64,
+    decode_errors: u64,
+    rtt_samples: Vec<f32>,
+    packet_loss_samples: Vec<f32>,
+    sent_kbps_samples: Vec<f32>,
+    received_kbps_samples: Vec<f32>,
+    deaths: u64,
+}
+
+struct LoadClient {
+    client: RenetClient,
+    input: PlayerInput,
+    next_direction_change: Instant,
+    step: usize,
+}
+
+impl LoadClient {
+    fn new(index: usize, server_addr: SocketAddr) -> Self {
+        let socket = UdpSocket::bind("0.0.0.0:0").unwrap();
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let client_id = current_time.as_millis() as u64 + index as u64;
+        let connection_data = ConnectionData {
+            name: format!("Load {}", index),
+            ..Default::default()
+        };
+        let authentication = ClientAuthentication::Unsecure {
+            client_id,
+            protocol_id: PROTOCOL_ID,
+            server_addr,
+            user_data: Some(connection_data.to_user_data()),
+        };
+        let client = RenetClient::new(
+            current_time,
+            socket,
+            client_connection_config(),
+            authentication,
+        )
+        .unwrap();
+
+        Self {
+            client,
+            input: PlayerInput::default(),
+            next_direction_change: Instant::now(),
+            step: 0,
+        }
+    }
+
+    fn drive(&mut self, script: Script, now: Instant, rng: &mut impl Rng) {
+        if now < self.next_direction_change {
+            return;
+        }
+        self.next_direction_change = now + DIRECTION_CHANGE;
+        self.step += 1;
+
+        self.input = match script {
+            Script::Random => PlayerInput {
+                up: rng.gen_bool(0.5),
+                down: rng.gen_bool(0.5),
+                left: rng.gen_bool(0.5),
+                right: rng.gen_bool(0.5),
+            },
+            // walk a square: up, right, down, left
+            Script::Circle => PlayerInput {
+                up: self.step % 4 == 0,
+                right: self.step % 4 == 1,
+                down: self.step % 4 == 2,
+                left: self.step % 4 == 3,
+            },
+        };
+    }
+
+    fn receive(&mut self, stats: &mut Stats) {
+        while let Some(message) = self.client.receive_message(ServerChannel::ServerMessages) {
+            stats.message_bytes += message.len() as u64;
+            match bincode::deserialize::<ServerMessages>(&message) {
+                Ok(server_message) => {
+                    if let ServerMessages::PlayerKilled { .. } = server_message {
+                        stats.deaths += 1;
+                        let respawn = bincode::serialize(&PlayerCommand::Respawn).unwrap();
+                        self.client.send_message(ClientChannel::Command, respawn);
+                    }
+                    *stats.messages.entry(server_message.kind()).or_default() += 1;
+                }
+                Err(_) => stats.decode_errors += 1,
+            }
+        }
+
+        while let Some(message) = self
+            .client
+            .receive_message(ServerChannel::NetworkedEntities)
+        {
+            stats.sync_bytes += message.len() as u64;
+            match bincode::deserialize::<NetworkedEntities>(&message) {
+                Ok(_) => stats.sync_messages += 1,
+                Err(_) => stats.decode_errors += 1,
+            }
+        }
+    }
+}
+
+fn main() {
+    let args = match LoadTestArgs::from_args(std::env::args()) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    println!(
+        "Connecting {} clients to {} for {:?}.",
+        args.clients, args.server_addr, args.duration
+    );
+
+    let mut rng = rand::thread_rng();
+    let mut clients: Vec<LoadClient> = (0..args.clients)
+        .map(|index| LoadClient::new(index, args.server_addr))
+        .collect();
+    let mut stats = Stats::default();
+
+    let start = Instant::now();
+    let mut last_tick = start;
+    let mut last_sample = start;
+    while start.elapsed() < args.duration {
+        let now = Instant::now();
+        let delta = now - last_tick;
+        last_tick = now;
+        let sample = now - last_sample >= Duration::from_secs(1);
+        if sample {
+            last_sample = now;
+        }
+
+        for load_client in clients.iter_mut() {
+            if let Err(e) = load_client.client.update(delta) {
+                println!("Client {} error: {}", load_client.client.client_id(), e);
+                continue;
+            }
+
+            if load_client.client.is_connected() {
+                load_client.drive(args.script, now, &mut rng);
+                let input = bincode::serialize(&load_client.input).unwrap();
+                load_client.client.send_message(ClientChannel::Input, input);
+                load_client.receive(&mut stats);
+
+                if sample {
+                    let network_info = load_client.client.network_info();
+                    stats.rtt_samples.push(network_info.rtt);
+                    stats.packet_loss_samples.push(network_info.packet_loss);
+                    stats
+                        .sent_kbps_samples
+                        .push(network_info.sent_bandwidth_kbps);
+                    stats
+                        .received_kbps_samples
+                        .push(network_info.received_bandwidth_kbps);
+                }
+            }
+
+            if let Err(e) = load_client.client.send_packets() {
+                println!("Client {} error: {}", load_client.client.client_id(), e);
+            }
+        }
+
+        thread::sleep(TICK.saturating_sub(now.elapsed()));
+    }
+
+    let connected = clients
+        .iter()
+        .filter(|load_client| load_client.client.is_connected())
+        .count();
+    let leave = bincode::serialize(&PlayerCommand::Leave).unwrap();
+    for load_client in clients.iter_mut() {
+        if load_client.client.is_connected() {
+            // the server removes the cell and frees the slot right away, as for a quitting player
+            load_client
+                .client
+                .send_message(ClientChannel::Command, leave.clone());
+        } else {
+            load_client.client.disconnect();
+        }
+        // flush now so the server hears about it before we exit
+        let _ = load_client.client.send_packets();
+    }
+
+    report(&stats, start.elapsed(), connected, args.clients);
+}
+
+fn report(stats: &Stats, elapsed: Duration, connected: usize, clients: usize) {
+    let seconds = elapsed.as_secs_f64();
+    let total_messages: u64 = stats.messages.values().sum();
+
+    println!();
+    println!(
+        "Ran for {:.1}s, {}/{} clients connected at the end.",
+        seconds, connected, clients
+    );
+    println!(
+        "Deaths: {}, decode errors: {}",
+        stats.deaths, stats.decode_errors
+    );
+    println!();
+    println!("{:<20} {:>10} {:>10}", "message", "count", "per sec");
+    for (kind, count) in stats.messages.iter() {
+        println!(
+            "{:<20} {:>10} {:>10.1}",
+            kind,
+            count,
+            *count as f64 / seconds
+        );
+    }
+    println!(
+        "{:<20} {:>10} {:>10.1}",
+        "NetworkedEntities",
+        stats.sync_messages,
+        stats.sync_messages as f64 / seconds
+    );
+    println!(
+        "{:<20} {:>10} {:>10.1}",
+        "total",
+        total_messages + stats.sync_messages,
+        (total_messages + stats.sync_messages) as f64 / seconds
+    );
+    println!();
+    println!(
+        "Payload received: messages {:.1} KB/s, sync {:.1} KB/s",
+        stats.message_bytes as f64 / 1024.0 / seconds,
+        stats.sync_bytes as f64 / 1024.0 / seconds
+    );
+    println!(
+        "Link per client: sent {:.1} kbps, received {:.1} kbps, packet loss {:.2}%",
+        mean(&stats.sent_kbps_samples),
+        mean(&stats.received_kbps_samples),
+        mean(&stats.packet_loss_samples) * 100.0
+    );
+
+    let mut rtt = stats.rtt_samples.clone();
+    rtt.sort_by(|a, b| a.total_cmp(b));
+    println!(
+        "RTT ms: mean {:.1}, p50 {:.1}, p95 {:.1}, max {:.1}",
+        mean(&rtt),
+        percentile(&rtt, 0.5),
+        percentile(&rtt, 0.95),
+        rtt.last().copied().unwrap_or_default()
+    );
+}
+
+fn mean(samples: &[f32]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    samples.iter().sum::<f32>() / samples.len() as f32
+}
+
+// samples must already be sorted
+fn percentile(samples: &[f32], percentile: f32) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let index = ((samples.len() - 1) as f32 * percentile).round() as usize;
+    samples[index]
+}
@@ -1,32 +1,59 @@
-use std::{net::UdpSocket, time::SystemTime};
+use std::{net::UdpSocket, process, time::SystemTime};
 
 use bevy::{
-    app::AppExit, diagnostic::LogDiagnosticsPlugin, prelude::*, window::exit_on_all_closed,
+    app::AppExit, diagnostic::LogDiagnosticsPlugin, log::LogPlugin, prelude::*, time::TimeSystem,
+    window::exit_on_all_closed,
 };
 use bevy_egui::EguiPlugin;
-use bevy_rapier3d::prelude::{ActiveEvents, *};
+use bevy_rapier3d::prelude::*;
 use bevy_renet::{
-    renet::{RenetServer, ServerAuthentication, ServerConfig, ServerEvent},
+    renet::{RenetServer, ServerAuthentication, ServerConfig},
     RenetServerPlugin,
 };
+use cagario::access::{disconnect_refused, reload_ban_list, BanList, Refusals};
+use cagario::admin::{admin_console_system, AdminConsole};
+use cagario::arena::Arenas;
+use cagario::bot::{bot_think_system, maintain_bots, BotRoster};
+use cagario::chat::{server_chat_system, ServerChat};
+use cagario::leaderboard::{server_send_leaderboard, LEADERBOARD_INTERVAL};
+use cagario::logging::init_logging;
+use cagario::metrics::{
+    begin_metrics_tick, count_eats, end_metrics_tick, publish_metrics, ServerMetrics,
+};
+use cagario::net_debug::{server_network_debug, track_debug_clients, ServerNetworkDebug};
+use cagario::profile::{
+    close_profiles_on_exit, save_profiles, track_profile_connections, track_profile_games,
+    ProfileStore,
+};
+use cagario::replay::{
+    advance_replay_clock, close_replay_on_exit, rotate_replay_per_match, ReplayRecorder,
+};
+use cagario::rng::ServerRng;
+use cagario::round::{count_round_kills, reset_arenas, tick_round, Rounds};
+use cagario::server::{move_players_system, server_network_sync, server_update_system};
+use cagario::session::{expire_sessions, SessionExpired, Sessions};
+use cagario::settings::ServerSettings;
+use cagario::spawn::SpawnPlacement;
+use cagario::validation::{enforce_violation_scores, AntiCheat};
 use cagario::{
-    cells::{spawn_spheres, Cell, NpcCell},
-    physics::{PhysicsBundle, PhysicsPlugin},
-    player::{update_player_cell_size, INITIAL_PLAYER_SIZE},
-    server_connection_config, ClientChannel, Player, PlayerCommand, PlayerInput, ServerChannel,
-    ServerMessages, PROTOCOL_ID,
+    cells::{spawn_spheres, Cell},
+    physics::PhysicsPlugin,
+    player::update_player_cell_size,
+    server_connection_config, PROTOCOL_ID,
 };
 
 use bevy_inspector_egui::WorldInspectorPlugin;
 
-const PLAYER_MOVE_SPEED: f32 = 20.0;
-
-fn new_renet_server() -> RenetServer {
+fn new_renet_server(max_clients: usize) -> RenetServer {
     let server_addr = "127.0.0.1:5000".parse().unwrap();
     let socket = UdpSocket::bind(server_addr).unwrap();
     let connection_config = server_connection_config();
-    let server_config =
-        ServerConfig::new(64, PROTOCOL_ID, server_addr, ServerAuthentication::Unsecure);
+    let server_config = ServerConfig::new(
+        max_clients,
+        PROTOCOL_ID,
+        server_addr,
+        ServerAuthentication::Unsecure,
+    );
     let current_time = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap();
@@ -34,23 +61,31 @@ fn new_renet_server() -> RenetServer {
 }
 
 use cagario::*;
-use rand::Rng;
 use simula_viz::{grid::GridPlugin, lines::LinesPlugin};
 use smooth_bevy_cameras::LookTransformPlugin;
 
 fn main() {
+    let settings = ServerSettings::from_args(std::env::args());
+    init_logging(&settings.log);
+    for warning in settings.warnings.iter() {
+        warn!("{}", warning);
+    }
     let mut app = App::new();
 
-    app.add_plugins(DefaultPlugins.set(WindowPlugin {
-        window: WindowDescriptor {
-            width: WINDOW_WIDTH,
-            height: WINDOW_HEIGHT,
-            title: "Cagario - Server".to_string(),
-            resizable: false,
-            ..default()
-        },
-        ..default()
-    }));
+    app.add_plugins(
+        DefaultPlugins
+            .set(WindowPlugin {
+                window: WindowDescriptor {
+                    width: WINDOW_WIDTH,
+                    height: WINDOW_HEIGHT,
+                    title: "Cagario - Server".to_string(),
+                    resizable: false,
+                    ..default()
+                },
+                ..default()
+            })
+            .disable::<LogPlugin>(),
+    );
     app.add_plugin(RenetServerPlugin::default());
     app.add_plugin(RapierPhysicsPlugin::<NoUserData>::default());
     app.add_plugin(RapierDebugRenderPlugin::default());
@@ -66,11 +101,65 @@ fn main() {
     app.add_plugin(LookTransformPlugin);
 
     app.insert_resource(ServerLobby::default());
-    app.insert_resource(new_renet_server());
+    app.init_resource::<SpawnPlacement>();
+    let arenas = match Arenas::from_settings(&settings) {
+        Ok(arenas) => arenas,
+        Err(e) => {
+            error!("{}", e);
+            process::exit(2);
+        }
+    };
+    let rng = ServerRng::from_settings(&settings);
+    if let Some(dir) = settings.record_dir.clone() {
+        let rooms = arenas.iter().map(|(_, room)| room.code.clone()).collect();
+        app.insert_resource(ReplayRecorder::new(dir, rng.seed(), rooms));
+    }
+    app.insert_resource(rng);
+    app.insert_resource(ServerChat::from_settings(&settings));
+    app.insert_resource(BanList::from_settings(&settings));
+    if let Some(profiles) = ProfileStore::from_settings(&settings) {
+        app.insert_resource(profiles);
+    }
+    if let Some(addr) = settings.metrics_addr {
+        match ServerMetrics::serve(addr) {
+            Ok(metrics) => {
+                info!("Serving metrics on http://{}/metrics", addr);
+                app.insert_resource(metrics);
+            }
+            Err(e) => error!("Failed to serve metrics on {}: {}", addr, e),
+        }
+    }
+    let rounds = Rounds::from_arenas(&arenas);
+    if !rounds.is_empty() {
+        app.insert_resource(rounds);
+        app.add_system(count_round_kills);
+        app.add_system(tick_round.after(count_round_kills));
+        // draws spawn points from ServerRng, so it gets a fixed slot like the other users
+        app.add_system(
+            reset_arenas
+                .after(tick_round)
+                .after(server_update_system)
+                .before(spawn_spheres),
+        );
+    }
+    app.insert_resource(new_renet_server(settings.max_clients));
+    app.insert_resource(arenas);
+    app.insert_resource(settings);
+    app.init_resource::<BotRoster>();
     app.register_type::<Cell>();
-    // app.insert_resource(RenetServerVisualizer::<200>::default());
+    app.init_resource::<ServerNetworkDebug>();
 
     app.add_system(server_update_system);
+    app.add_system_to_stage(CoreStage::First, advance_replay_clock.after(TimeSystem));
+    app.add_system_to_stage(CoreStage::First, begin_metrics_tick);
+    app.add_system_to_stage(CoreStage::Last, end_metrics_tick);
+    app.add_system(count_eats);
+    app.add_system(track_profile_connections.after(server_update_system));
+    app.add_system(track_profile_games);
+    app.add_system(save_profiles.after(track_profile_games));
+    app.add_system(publish_metrics);
+    // open the file before anything is sent to a newly connected client
+    app.add_system(rotate_replay_per_match.before(server_update_system));
 
     app.add_startup_system(spawn_scene);
     app.add_startup_system(spawn_grid_lines);
@@ -78,20 +167,49 @@ fn main() {
     // app.add_system(camera_follow);
     app.add_system(server_network_sync);
     app.add_system(move_players_system);
-    app.add_system(spawn_spheres);
+    // systems drawing from ServerRng run in a fixed order so a seed replays the same match
+    app.add_system(spawn_spheres.after(server_update_system));
+    app.add_system(server_send_leaderboard);
+    app.add_system(server_chat_system);
+    app.init_resource::<Refusals>();
+    app.add_system(reload_ban_list);
+    app.add_system(disconnect_refused);
+    app.init_resource::<AntiCheat>();
+    app.init_resource::<Sessions>();
+    app.add_event::<SessionExpired>();
+    app.add_system(expire_sessions.after(server_update_system));
+    app.add_system(enforce_violation_scores.after(server_update_system));
+    app.insert_resource(AdminConsole::stdin());
+    app.add_system(
+        admin_console_system
+            .after(server_update_system)
+            .before(spawn_spheres),
+    );
+    println!("Type help in this terminal for admin commands.");
+    app.add_system(maintain_bots.after(spawn_spheres));
+    app.add_system(bot_think_system.before(move_players_system));
     app.add_system(update_player_cell_size);
     // app.add_system(move_players_system);
     // app.add_system(update_projectiles_system);
-    // app.add_system(update_visulizer_system);
+    app.add_system(track_debug_clients);
+    app.add_system(server_network_debug);
     // app.add_system(despawn_projectile_system);
     // app.add_system_to_stage(CoreStage::PostUpdate, projectile_on_removal_system);
     app.add_system_to_stage(
         CoreStage::PostUpdate,
         disconnect_clients_on_exit.after(exit_on_all_closed),
     );
+    app.add_system_to_stage(
+        CoreStage::PostUpdate,
+        close_replay_on_exit.after(exit_on_all_closed),
+    );
+    app.add_system_to_stage(
+        CoreStage::PostUpdate,
+        close_profiles_on_exit.after(exit_on_all_closed),
+    );
 
     app.insert_resource(Game {
-        cell_spawn_timer: Timer::from_seconds(0.2, TimerMode::Repeating),
+        leaderboard_timer: Timer::from_seconds(LEADERBOARD_INTERVAL, TimerMode::Repeating),
     });
 
     // app.add_startup_system(setup_level);
@@ -100,165 +218,6 @@ fn main() {
     app.run();
 }
 
-#[allow(clippy::too_many_arguments)]
-fn server_update_system(
-    mut server_events: EventReader<ServerEvent>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut lobby: ResMut<ServerLobby>,
-    mut server: ResMut<RenetServer>,
-    // mut visualizer: ResMut<RenetServerVisualizer<200>>,
-    players: Query<(Entity, &Player, &Transform)>,
-    npc_cells: Query<(Entity, &Cell, &Transform), With<NpcCell>>,
-) {
-    for event in server_events.iter() {
-        match event {
-            ServerEvent::ClientConnected(id, _) => {
-                println!("Player {} connected.", id);
-                // visualizer.add_client(*id);
-
-                // Initialize other players for this new client
-                for (entity, player, transform) in players.iter() {
-                    let translation: [f32; 3] = transform.translation.into();
-                    let message = bincode::serialize(&ServerMessages::PlayerCreate {
-                        id: player.id,
-                        entity,
-                        translation,
-                    })
-                    .unwrap();
-                    server.send_message(*id, ServerChannel::ServerMessages, message);
-                }
-
-                // initialize npc cells already spawned
-                for (entity, cell, transform) in npc_cells.iter() {
-                    let translation: [f32; 3] = transform.translation.into();
-                    let message = bincode::serialize(&ServerMessages::SpawnNpcCell {
-                        entity,
-                        size: cell.size,
-                        translation,
-                    })
-                    .unwrap();
-                    server.send_message(*id, ServerChannel::ServerMessages, message);
-                }
-
-                let mut rng = rand::thread_rng();
-
-                // not using entire field size so we can se our players from the server window
-                // let x = rng.gen_range(-FIELD_SIZE / 2.0..FIELD_SIZE / 2.0) as f32;
-                // let z = rng.gen_range(-FIELD_SIZE / 2.0..FIELD_SIZE / 2.0) as f32;
-                let x = rng.gen_range(-50.0 / 2.0..50.0 / 2.0) as f32;
-                let z = rng.gen_range(-50.0 / 2.0..50.0 / 2.0) as f32;
-                let rand_transform = Transform::from_xyz(x, 0.0, z);
-                // let rand_transform = Transform::from_xyz(0.0, 0.0, 0.0);
-                let player_entity = commands
-                    .spawn(PbrBundle {
-                        mesh: meshes.add(Mesh::from(shape::Icosphere {
-                            radius: INITIAL_PLAYER_SIZE,
-                            subdivisions: 4,
-                        })),
-                        material: materials.add(Color::rgb(0.5, 0.5, 1.0).into()),
-                        transform: rand_transform,
-                        ..Default::default()
-                    })
-                    .insert(Player { id: *id })
-                    .insert(Cell {
-                        size: INITIAL_PLAYER_SIZE,
-                    })
-                    .insert(Name::new("Player"))
-                    .insert(PlayerInput::default())
-                    .insert(Velocity::default())
-                    .insert(ActiveEvents::COLLISION_EVENTS)
-                    .insert(PhysicsBundle::moving_entity())
-                    .insert(Collider::ball(INITIAL_PLAYER_SIZE / 2.0))
-                    .id();
-
-                lobby.players.insert(*id, player_entity);
-
-                let translation: [f32; 3] = rand_transform.translation.into();
-                let message = bincode::serialize(&ServerMessages::PlayerCreate {
-                    id: *id,
-                    entity: player_entity,
-                    translation,
-                })
-                .unwrap();
-                server.broadcast_message(ServerChannel::ServerMessages, message);
-            }
-            ServerEvent::ClientDisconnected(id) => {
-                println!("Player {} disconnected.", id);
-                // visualizer.remove_client(*id);
-                if let Some(player_entity) = lobby.players.remove(id) {
-                    commands.entity(player_entity).despawn();
-                }
-
-                let message =
-                    bincode::serialize(&ServerMessages::PlayerRemove { id: *id }).unwrap();
-                server.broadcast_message(ServerChannel::ServerMessages, message);
-            }
-        }
-    }
-
-    for client_id in server.clients_id().into_iter() {
-        while let Some(message) = server.receive_message(client_id, ClientChannel::Command) {
-            let command: PlayerCommand = bincode::deserialize(&message).unwrap();
-            match command {
-                PlayerCommand::BasicAttack { mut cast_at } => {
-                    println!(
-                        "Received basic attack from client {}: {:?}",
-                        client_id, cast_at
-                    );
-
-                    if let Some(player_entity) = lobby.players.get(&client_id) {
-                        if let Ok((_, _, player_transform)) = players.get(*player_entity) {
-                            cast_at[1] = player_transform.translation[1];
-
-                            let direction =
-                                (cast_at - player_transform.translation).normalize_or_zero();
-                            let mut translation = player_transform.translation + (direction * 0.7);
-                            translation[1] = 1.0;
-                        }
-                    }
-                }
-            }
-        }
-        while let Some(message) = server.receive_message(client_id, ClientChannel::Input) {
-            let input: PlayerInput = bincode::deserialize(&message).unwrap();
-            if let Some(player_entity) = lobby.players.get(&client_id) {
-                commands.entity(*player_entity).insert(input);
-            }
-        }
-    }
-}
-
-fn move_players_system(mut query: Query<(&mut Transform, &PlayerInput)>, time: Res<Time>) {
-    for (mut transform, input) in query.iter_mut() {
-        let x = (input.right as i8 - input.left as i8) as f32;
-        let y = (input.down as i8 - input.up as i8) as f32;
-        let direction = Vec2::new(x, y).normalize_or_zero();
-
-        transform.translation +=
-            Vec3::new(direction.x, 0.0, direction.y) * PLAYER_MOVE_SPEED * time.delta_seconds();
-    }
-}
-
-#[allow(clippy::type_complexity)]
-fn server_network_sync(
-    mut server: ResMut<RenetServer>,
-    query: Query<(Entity, &Transform), With<Player>>,
-) {
-    let mut networked_entities = NetworkedEntities::default();
-    for (entity, transform) in query.iter() {
-        networked_entities.entities.push(entity);
-        networked_entities
-            .translations
-            .push(transform.translation.into());
-        networked_entities.scalings.push(transform.scale.into());
-    }
-
-    let sync_message = bincode::serialize(&networked_entities).unwrap();
-    server.broadcast_message(ServerChannel::NetworkedEntities, sync_message);
-}
-
 pub fn setup_simple_camera(mut commands: Commands) {
     // camera
     commands.spawn(Camera3dBundle {
@@ -0,0 +1,212 @@
+use bevy::prelude::*;
+
+use crate::{
+    arena::Arena,
+    cells::Cell,
+    player::spawn_player,
+    rng::ServerRng,
+    server::ServerSender,
+    settings::ServerSettings,
+    spawn::SpawnPlacement,
+    team::{assign_team, Team},
+    Player, PlayerInput, ServerChannel, ServerLobby, ServerMessages,
+};
+
+pub const BOT_VIEW_RANGE: f32 = 40.0;
+// client ids are connection timestamps, counting down from the top keeps bots clear of them
+pub const FIRST_BOT_ID: u64 = u64::MAX;
+
+#[derive(Debug, Clone, Copy)]
+pub struct NearbyCell {
+    pub translation: Vec3,
+    pub size: f32,
+    pub is_player: bool,
+}
+
+/// What a bot can see this tick.
+#[derive(Debug)]
+pub struct BotView<'a> {
+    pub translation: Vec3,
+    pub size: f32,
+    pub nearby: &'a [NearbyCell],
+}
+
+/// Decides how a bot moves, called once per tick for every bot.
+pub trait BotBrain: Send + Sync + 'static {
+    fn think(&mut self, view: &BotView) -> PlayerInput;
+}
+
+/// A server-side player without a renet connection.
+///
+/// Bots are regular `Player` cells, so they eat and get eaten exactly like
+/// networked players. Only their input comes from the brain instead of the wire.
+#[derive(Component)]
+pub struct Bot {
+    pub brain: Box<dyn BotBrain>,
+}
+
+/// Runs from the closest bigger cell, otherwise chases the closest smaller one.
+#[derive(Debug, Default)]
+pub struct SeekFoodBrain;
+
+impl BotBrain for SeekFoodBrain {
+    fn think(&mut self, view: &BotView) -> PlayerInput {
+        let closest = |bigger: bool| {
+            view.nearby
+                .iter()
+                .filter(|cell| (cell.size > view.size) == bigger && cell.size != view.size)
+                .min_by(|a, b| {
+                    let a = a.translation.distance_squared(view.translation);
+                    let b = b.translation.distance_squared(view.translation);
+                    a.total_cmp(&b)
+                })
+        };
+
+        if let Some(threat) = closest(true) {
+            return input_towards(view.translation - threat.translation);
+        }
+        if let Some(food) = closest(false) {
+            return input_towards(food.translation - view.translation);
+        }
+        PlayerInput::default()
+    }
+}
+
+/// Turns a direction on the floor plane into the digital input players send.
+pub fn input_towards(direction: Vec3) -> PlayerInput {
+    let direction = Vec2::new(direction.x, direction.z).normalize_or_zero();
+    // a small dead zone keeps bots from zig-zagging on the minor axis
+    PlayerInput {
+        up: direction.y < -0.3,
+        down: direction.y > 0.3,
+        left: direction.x < -0.3,
+        right: direction.x > 0.3,
+    }
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct BotRoster {
+    pub ids: Vec<u64>,
+}
+
+/// Keeps `ServerSettings::bots` bots in the arena, respawning any that were eaten.
+#[allow(clippy::too_many_arguments)]
+pub fn maintain_bots(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut lobby: ResMut<ServerLobby>,
+    mut server: ServerSender,
+    mut roster: ResMut<BotRoster>,
+    settings: Res<ServerSettings>,
+    spawn_placement: Res<SpawnPlacement>,
+    mut rng: ResMut<ServerRng>,
+    cells: Query<(&Transform, &Cell, &Arena)>,
+) {
+    while roster.ids.len() < settings.bots {
+        let id = FIRST_BOT_ID - roster.ids.len() as u64;
+        roster.ids.push(id);
+        lobby.names.insert(id, format!("Bot {}", roster.ids.len()));
+        assign_team(&mut lobby, id, settings.teams);
+    }
+
+    let removed: Vec<u64> = roster.ids.drain(settings.bots..).collect();
+    for id in removed {
+        lobby.names.remove(&id);
+        lobby.teams.remove(&id);
+        let arena = server.arenas().arena_of(id);
+        server.arenas_mut().leave(id);
+        if let Some(bot_entity) = lobby.players.remove(&id) {
+            commands.entity(bot_entity).despawn();
+            let message = bincode::serialize(&ServerMessages::PlayerRemove { id }).unwrap();
+            server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+        }
+    }
+
+    for id in roster.ids.iter() {
+        if lobby.players.contains_key(id) {
+            continue;
+        }
+        // bots are spread over the arenas like clients without a room code
+        let arena = if server.arenas().is_assigned(*id) {
+            server.arenas().arena_of(*id)
+        } else {
+            server.arenas_mut().join(*id, "")
+        };
+        let occupied: Vec<(Vec3, f32)> = cells
+            .iter()
+            .filter(|(_, _, cell_arena)| **cell_arena == arena)
+            .map(|(transform, cell, _)| (transform.translation, cell.size))
+            .collect();
+        let bot_entity = spawn_player(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &mut lobby,
+            &mut server,
+            &mut rng,
+            &spawn_placement,
+            &occupied,
+            *id,
+            arena,
+        );
+        commands.entity(bot_entity).insert(Bot {
+            brain: Box::new(SeekFoodBrain),
+        });
+    }
+}
+
+/// Fills in every bot's view and asks its brain for input.
+///
+/// Teammates are left out of the view, they can't eat the bot nor be eaten by it.
+/// So are cells in other arenas, which share the same coordinates.
+#[allow(clippy::type_complexity)]
+pub fn bot_think_system(
+    mut bots: Query<(
+        Entity,
+        &Transform,
+        &Cell,
+        &Arena,
+        Option<&Team>,
+        &mut Bot,
+        &mut PlayerInput,
+    )>,
+    cells: Query<(
+        Entity,
+        &Transform,
+        &Cell,
+        &Arena,
+        Option<&Team>,
+        Option<&Player>,
+    )>,
+) {
+    let mut nearby = Vec::new();
+    for (bot_entity, bot_transform, bot_cell, bot_arena, bot_team, mut bot, mut input) in
+        bots.iter_mut()
+    {
+        nearby.clear();
+        nearby.extend(
+            cells
+                .iter()
+                .filter(|(entity, transform, _, cell_arena, team, _)| {
+                    *entity != bot_entity
+                        && *cell_arena == bot_arena
+                        && !matches!((bot_team, team), (Some(a), Some(b)) if a == b)
+                        && transform.translation.distance(bot_transform.translation)
+                            <= BOT_VIEW_RANGE
+                })
+                .map(|(_, transform, cell, _, _, player)| NearbyCell {
+                    translation: transform.translation,
+                    size: cell.size,
+                    is_player: player.is_some(),
+                }),
+        );
+
+        let view = BotView {
+            translation: bot_transform.translation,
+            size: bot_cell.size,
+            nearby: &nearby,
+        };
+        *input = bot.brain.think(&view);
+    }
+}
@@ -1,9 +1,11 @@
 use bevy::prelude::*;
 use bevy_rapier3d::prelude::{ActiveEvents, Collider};
-use bevy_renet::renet::RenetServer;
 use rand::*;
 
-use crate::{physics::PhysicsBundle, Game, GameState, ServerChannel, ServerMessages, FIELD_SIZE};
+use crate::{
+    arena::Arena, physics::PhysicsBundle, rng::ServerRng, server::ServerSender,
+    spawn::SpawnPlacement, GameState, ServerChannel, ServerMessages,
+};
 
 #[derive(Resource)]
 pub struct MaxSpheres(usize);
@@ -33,50 +35,88 @@ pub fn spawn_spheres(
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
     time: Res<Time>,
-    mut server: ResMut<RenetServer>,
+    mut server: ServerSender,
 
     // max_spheres: Res<MaxSpheres>,
-    cell_query: Query<(&mut Transform, With<NpcCell>)>,
-    mut game: ResMut<Game>,
+    cell_query: Query<&Arena, With<NpcCell>>,
+    occupied_cells: Query<(&Transform, &Cell, &Arena)>,
+    spawn_placement: Res<SpawnPlacement>,
+    mut rng: ResMut<ServerRng>,
 ) {
-    // create a random number generator
-    let mut rng = rand::thread_rng();
-
-    game.cell_spawn_timer.tick(time.delta());
-    // for (mut transform, mut spawner) in cell_query.iter_mut() {
-    // check if the maximum number of spheres has been reached
-    if cell_query.iter().count() <= MAX_SPHERES {
-        if game.cell_spawn_timer.just_finished() {
-            // generate random x, y, and z coordinates for the sphere's position
-            let x = rng.gen_range(-FIELD_SIZE / 2.0..FIELD_SIZE / 2.0) as f32;
-            let z = rng.gen_range(-FIELD_SIZE / 2.0..FIELD_SIZE / 2.0) as f32;
-            let size = rng.gen_range(0.4..1.4) as f32;
-            let entity = commands
-                .spawn(PbrBundle {
-                    transform: Transform::from_translation(Vec3::new(x, -size / 2.0, z)),
-                    mesh: meshes.add(Mesh::from(shape::Icosphere {
-                        radius: size,
-                        subdivisions: 4,
-                    })),
-                    material: materials.add(Color::rgb(x, z, size).into()),
-                    ..Default::default()
-                })
-                .insert(Name::new("Cell"))
-                .insert(NpcCell)
-                .insert(Cell { size })
-                .insert(ActiveEvents::COLLISION_EVENTS)
-                .insert(Collider::ball(size / 2.0))
-                .insert(PhysicsBundle::moving_entity())
-                .id();
-
-            let message = bincode::serialize(&ServerMessages::SpawnNpcCell {
-                // id: player.id,
-                size,
-                entity,
-                translation: [x, -size / 2.0, z],
-            })
-            .unwrap();
-            server.broadcast_message(ServerChannel::ServerMessages, message);
+    // every arena spawns at its own rate
+    let due = server.arenas_mut().tick_food_timers(time.delta());
+    for arena in due {
+        let max_food = server.arenas().settings(arena).max_food;
+        // for (mut transform, mut spawner) in cell_query.iter_mut() {
+        // check if the maximum number of spheres has been reached
+        let food = cell_query
+            .iter()
+            .filter(|cell_arena| **cell_arena == arena)
+            .count();
+        if food <= max_food {
+            let occupied: Vec<(Vec3, f32)> = occupied_cells
+                .iter()
+                .filter(|(_, _, cell_arena)| **cell_arena == arena)
+                .map(|(transform, cell, _)| (transform.translation, cell.size))
+                .collect();
+            spawn_food(
+                &mut commands,
+                &mut meshes,
+                &mut materials,
+                &mut server,
+                &mut rng,
+                &spawn_placement,
+                &occupied,
+                arena,
+            );
         }
     }
 }
+
+/// Spawns one food cell in `arena` and tells its clients.
+///
+/// Returns `None` when the field is too crowded around every candidate point.
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_food(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    server: &mut ServerSender,
+    rng: &mut ServerRng,
+    spawn_placement: &SpawnPlacement,
+    occupied: &[(Vec3, f32)],
+    arena: Arena,
+) -> Option<(Entity, Vec3, f32)> {
+    let position = spawn_placement.food_spawn_point(&mut *rng, occupied)?;
+    let (x, z) = (position.x, position.z);
+    let size = rng.gen_range(0.4..1.4) as f32;
+    let translation = Vec3::new(x, -size / 2.0, z);
+    let entity = commands
+        .spawn(PbrBundle {
+            transform: Transform::from_translation(translation),
+            mesh: meshes.add(Mesh::from(shape::Icosphere {
+                radius: size,
+                subdivisions: 4,
+            })),
+            material: materials.add(Color::rgb(x, z, size).into()),
+            ..Default::default()
+        })
+        .insert(Name::new("Cell"))
+        .insert(NpcCell)
+        .insert(Cell { size })
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(Collider::ball(size / 2.0))
+        .insert(PhysicsBundle::moving_entity())
+        .insert(arena.collision_groups())
+        .insert(arena)
+        .id();
+
+    let message = bincode::serialize(&ServerMessages::SpawnNpcCell {
+        size,
+        entity,
+        translation: translation.into(),
+    })
+    .unwrap();
+    server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+    Some((entity, translation, size))
+}
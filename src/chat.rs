@@ -0,0 +1,350 @@
+use std::{collections::VecDeque, fs, path::Path};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_egui::{egui, EguiContext};
+use bevy_renet::renet::RenetClient;
+
+use crate::{
+    server::ServerSender,
+    settings::ServerSettings,
+    validation::{decode, AntiCheat, Violation},
+    ClientChannel, GameState, Player, ServerChannel, ServerLobby, ServerMessages,
+};
+
+pub const MAX_CHAT_LENGTH: usize = 120;
+// messages a client can send in a burst before being throttled
+pub const CHAT_BURST: f32 = 3.0;
+// seconds to earn back one message of the burst
+pub const CHAT_REFILL: f32 = 2.0;
+// lines kept in the client's chat box
+pub const CHAT_HISTORY: usize = 50;
+
+/// Decides what happens to a chat line before it is relayed.
+///
+/// Returns the text to send, possibly rewritten, or `None` to drop it.
+pub trait ChatFilter: Send + Sync + 'static {
+    fn filter(&self, client_id: u64, text: &str) -> Option<String>;
+}
+
+/// Masks blocked words with asterisks, ignoring case.
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    words: Vec<String>,
+}
+
+impl Blocklist {
+    pub fn new(words: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            words: words
+                .into_iter()
+                .map(|word| word.trim().to_lowercase())
+                .filter(|word| !word.is_empty())
+                .collect(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> std::io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Ok(Self::new(contents.lines().map(str::to_string)))
+    }
+}
+
+impl ChatFilter for Blocklist {
+    fn filter(&self, _client_id: u64, text: &str) -> Option<String> {
+        let filtered: Vec<String> = text
+            .split(' ')
+            .map(|word| {
+                let bare = word
+                    .trim_matches(|c: char| !c.is_alphanumeric())
+                    .to_lowercase();
+                if self.words.contains(&bare) {
+                    "*".repeat(word.chars().count())
+                } else {
+                    word.to_string()
+                }
+            })
+            .collect();
+        Some(filtered.join(" "))
+    }
+}
+
+/// What the rate limit makes of one chat line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChatRate {
+    Allowed,
+    // dropped, `notify` is only set for the first one until the allowance refills
+    Throttled { notify: bool },
+}
+
+/// Server side chat state: the filter hook and per-client rate limits.
+#[derive(Resource)]
+pub struct ServerChat {
+    pub filter: Box<dyn ChatFilter>,
+    pub range: Option<f32>,
+    allowance: HashMap<u64, f32>,
+    // clients already told they are sending too fast
+    throttled: HashSet<u64>,
+}
+
+impl ServerChat {
+    pub fn new(filter: Box<dyn ChatFilter>, range: Option<f32>) -> Self {
+        Self {
+            filter,
+            range,
+            allowance: HashMap::default(),
+            throttled: HashSet::default(),
+        }
+    }
+
+    pub fn from_settings(settings: &ServerSettings) -> Self {
+        let blocklist = match &settings.chat_blocklist {
+            Some(path) => Blocklist::from_file(path).unwrap_or_else(|e| {
+                error!("Failed to read chat blocklist {}: {}", path.display(), e);
+                Blocklist::default()
+            }),
+            None => Blocklist::default(),
+        };
+        Self::new(Box::new(blocklist), settings.chat_range)
+    }
+
+    /// Takes one line from the client's allowance, a token bucket that starts full.
+    pub fn allow(&mut self, client_id: u64) -> ChatRate {
+        let allowance = self.allowance.entry(client_id).or_insert(CHAT_BURST);
+        if *allowance < 1.0 {
+            let notify = self.throttled.insert(client_id);
+            return ChatRate::Throttled { notify };
+        }
+        *allowance -= 1.0;
+        ChatRate::Allowed
+    }
+
+    /// Earns back `delta` seconds of allowance, a client that can send again gets a new notice.
+    pub fn refill(&mut self, delta: f32) {
+        let refill = delta / CHAT_REFILL;
+        for (client_id, allowance) in self.allowance.iter_mut() {
+            *allowance = (*allowance + refill).min(CHAT_BURST);
+            if *allowance >= 1.0 {
+                self.throttled.remove(client_id);
+            }
+        }
+    }
+}
+
+/// Trims, strips control characters and caps the length of a chat line.
+pub fn sanitize_chat(text: &str) -> String {
+    text.chars()
+        .filter(|c| !c.is_control())
+        .take(MAX_CHAT_LENGTH)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+pub fn server_chat_system(
+    time: Res<Time>,
+    mut chat: ResMut<ServerChat>,
+    mut server: ServerSender,
+    lobby: Res<ServerLobby>,
+    mut anti_cheat: ResMut<AntiCheat>,
+    players: Query<&Transform, With<Player>>,
+) {
+    chat.refill(time.delta_seconds());
+
+    for client_id in server.clients_id().into_iter() {
+        let _span = info_span!("client", id = client_id).entered();
+        while let Some(message) = server.receive_message(client_id, ClientChannel::Chat) {
+            let text: String = match decode(&message) {
+                Ok(text) => text,
+                Err(violation) => {
+                    anti_cheat.report(client_id, violation);
+                    continue;
+                }
+            };
+            let text = sanitize_chat(&text);
+            if text.is_empty() {
+                continue;
+            }
+            if let ChatRate::Throttled { notify } = chat.allow(client_id) {
+                anti_cheat.report(client_id, Violation::RateLimited);
+                if notify {
+                    let notice = bincode::serialize(&ServerMessages::Chat {
+                        id: None,
+                        name: "Server".to_string(),
+                        text: "You are sending messages too fast.".to_string(),
+                    })
+                    .unwrap();
+                    server.send_message(client_id, ServerChannel::ServerMessages, notice);
+                }
+                continue;
+            }
+            let text = match chat.filter.filter(client_id, &text) {
+                Some(text) => text,
+                None => continue,
+            };
+
+            let name = lobby
+                .names
+                .get(&client_id)
+                .cloned()
+                .unwrap_or_else(|| format!("Player {}", client_id % 10000));
+            let message = bincode::serialize(&ServerMessages::Chat {
+                id: Some(client_id),
+                name,
+                text,
+            })
+            .unwrap();
+
+            let sender_translation = lobby
+                .players
+                .get(&client_id)
+                .and_then(|entity| players.get(*entity).ok())
+                .map(|transform| transform.translation);
+            let arena = server.arenas().arena_of(client_id);
+            match (chat.range, sender_translation) {
+                (Some(range), Some(sender_translation)) => {
+                    for recipient in server.clients_id().into_iter() {
+                        if !server.arenas().is_member(recipient, arena) {
+                            continue;
+                        }
+                        // players without a cell (spectating, or eaten) still hear everything
+                        let in_range = lobby
+                            .players
+                            .get(&recipient)
+                            .and_then(|entity| players.get(*entity).ok())
+                            .map(|transform| {
+                                transform.translation.distance(sender_translation) <= range
+                            })
+                            .unwrap_or(true);
+                        if in_range {
+                            server.send_message(
+                                recipient,
+                                ServerChannel::ServerMessages,
+                                message.clone(),
+                            );
+                        }
+                    }
+                }
+                _ => server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message),
+            }
+        }
+    }
+
+    let clients = server.clients_id();
+    chat.allowance
+        .retain(|client_id, _| clients.contains(client_id));
+    chat.throttled
+        .retain(|client_id| clients.contains(client_id));
+}
+
+#[derive(Debug, Clone)]
+pub struct ChatLine {
+    pub name: String,
+    pub text: String,
+    pub notice: bool,
+}
+
+/// Chat received by the client, oldest first.
+#[derive(Debug, Default, Resource)]
+pub struct ChatLog {
+    pub lines: VecDeque<ChatLine>,
+}
+
+impl ChatLog {
+    pub fn push(&mut self, line: ChatLine) {
+        if self.lines.len() == CHAT_HISTORY {
+            self.lines.pop_front();
+        }
+        self.lines.push_back(line);
+    }
+}
+
+/// The line being typed. Movement keys are ignored while `typing` is set.
+#[derive(Debug, Default, Resource)]
+pub struct ChatInput {
+    pub text: String,
+    pub typing: bool,
+}
+
+/// A chat line the local player submitted.
+pub struct ChatSubmit(pub String);
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatLog>()
+            .init_resource::<ChatInput>()
+            .add_event::<ChatSubmit>()
+            .add_system_set(SystemSet::on_update(GameState::InGame).with_system(chat_window));
+    }
+}
+
+pub fn client_send_chat(mut submitted: EventReader<ChatSubmit>, mut client: ResMut<RenetClient>) {
+    for ChatSubmit(text) in submitted.iter() {
+        let message = bincode::serialize(text).unwrap();
+        client.send_message(ClientChannel::Chat, message);
+    }
+}
+
+/// Enter opens the input, Enter again sends, Escape cancels.
+///
+/// The text field only exists while typing, so it can never hold on to the
+/// keyboard while the player is moving.
+fn chat_window(
+    mut egui_context: ResMut<EguiContext>,
+    chat_log: Res<ChatLog>,
+    mut chat_input: ResMut<ChatInput>,
+    mut submitted: EventWriter<ChatSubmit>,
+) {
+    let ctx = egui_context.ctx_mut();
+    if !chat_input.typing
+        && !ctx.wants_keyboard_input()
+        && ctx.input().key_pressed(egui::Key::Enter)
+    {
+        chat_input.typing = true;
+        return;
+    }
+
+    egui::Window::new("Chat")
+        .anchor(egui::Align2::RIGHT_BOTTOM, [-10.0, -10.0])
+        .default_width(300.0)
+        .resizable(false)
+        .collapsible(false)
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(150.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for line in chat_log.lines.iter() {
+                        if line.notice {
+                            ui.colored_label(egui::Color32::LIGHT_BLUE, &line.text);
+                        } else {
+                            ui.label(format!("{}: {}", line.name, line.text));
+                        }
+                    }
+                });
+
+            if !chat_input.typing {
+                ui.weak("Press Enter to chat");
+                return;
+            }
+
+            let response = ui
+                .add(egui::TextEdit::singleline(&mut chat_input.text).desired_width(f32::INFINITY));
+            if response.lost_focus() {
+                if ui.input().key_pressed(egui::Key::Enter) {
+                    let text = sanitize_chat(&chat_input.text);
+                    if !text.is_empty() {
+                        submitted.send(ChatSubmit(text));
+                    }
+                }
+                chat_input.text.clear();
+                chat_input.typing = false;
+            } else {
+                response.request_focus();
+            }
+        });
+}
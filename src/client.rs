@@ -0,0 +1,297 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_rapier3d::prelude::{ActiveEvents, Collider, Velocity};
+use bevy_renet::renet::RenetClient;
+
+use crate::{
+    cells::{Cell, NpcCell},
+    chat::{ChatLine, ChatLog},
+    game_over::GameOverInfo,
+    kill_feed::KillFeed,
+    leaderboard::ClientLeaderboard,
+    net_debug::ClientNetworkDebug,
+    physics::PhysicsBundle,
+    player::INITIAL_PLAYER_SIZE,
+    profile::ClientProfile,
+    replay::ReplayPlayback,
+    round::ClientRound,
+    session::ClientSession,
+    team::team_color,
+    ClientChannel, ControlledPlayer, GameState, NetworkedEntities, PlayerCommand, PlayerInput,
+    ServerChannel, ServerMessages,
+};
+
+#[derive(Default, Resource)]
+pub struct NetworkMapping(pub HashMap<Entity, Entity>);
+
+#[derive(Debug)]
+pub struct PlayerInfo {
+    pub client_entity: Entity,
+    pub server_entity: Entity,
+}
+
+#[derive(Debug, Default, Resource)]
+pub struct ClientLobby {
+    pub players: HashMap<u64, PlayerInfo>,
+}
+
+/// Where the client reads server messages from: the live connection, or a replay.
+#[derive(SystemParam)]
+pub struct ClientReceiver<'w, 's> {
+    client: Option<ResMut<'w, RenetClient>>,
+    playback: Option<ResMut<'w, ReplayPlayback>>,
+    net_debug: Option<ResMut<'w, ClientNetworkDebug>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> ClientReceiver<'w, 's> {
+    /// The id of the local player, `None` when watching a replay.
+    pub fn client_id(&self) -> Option<u64> {
+        self.client.as_ref().map(|client| client.client_id())
+    }
+
+    pub fn receive_message<C: Into<u8>>(&mut self, channel: C) -> Option<Vec<u8>> {
+        if let Some(client) = self.client.as_mut() {
+            let channel = channel.into();
+            let message = client.receive_message(channel)?;
+            if let Some(debug) = self.net_debug.as_mut() {
+                let decode = debug.visible;
+                debug.stats.record(channel, &message, 1, decode);
+            }
+            return Some(message);
+        }
+        self.playback.as_mut()?.receive_message(channel)
+    }
+}
+
+pub fn client_send_input(player_input: Res<PlayerInput>, mut client: ResMut<RenetClient>) {
+    let input_message = bincode::serialize(&*player_input).unwrap();
+    client.send_message(ClientChannel::Input, input_message);
+}
+
+pub fn client_send_player_commands(
+    mut player_commands: EventReader<PlayerCommand>,
+    mut client: ResMut<RenetClient>,
+) {
+    for command in player_commands.iter() {
+        let command_message = bincode::serialize(command).unwrap();
+        client.send_message(ClientChannel::Command, command_message);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn client_sync_players(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut client: ClientReceiver,
+    mut lobby: ResMut<ClientLobby>,
+    mut game_state: ResMut<State<GameState>>,
+    mut network_mapping: ResMut<NetworkMapping>,
+    mut game_over_info: ResMut<GameOverInfo>,
+    mut leaderboard: ResMut<ClientLeaderboard>,
+    mut chat_log: ResMut<ChatLog>,
+    mut round: ResMut<ClientRound>,
+    mut profile: ResMut<ClientProfile>,
+    mut kill_feed: ResMut<KillFeed>,
+    mut session: Option<ResMut<ClientSession>>,
+    controlled_player: Query<Entity, &ControlledPlayer>,
+) {
+    let client_id = client.client_id();
+    while let Some(message) = client.receive_message(ServerChannel::ServerMessages) {
+        let server_message = bincode::deserialize(&message).unwrap();
+        match server_message {
+            ServerMessages::PlayerCreate {
+                id,
+                translation,
+                entity,
+                team,
+            } => {
+                debug!("Player {} connected.", id);
+                let [x, y, z] = translation;
+                let transform = Transform::from_xyz(x, y, z);
+                let mut client_entity = commands.spawn(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Icosphere {
+                        radius: INITIAL_PLAYER_SIZE,
+                        subdivisions: 4,
+                    })),
+                    material: materials.add(team_color(team).into()),
+                    transform,
+                    ..Default::default()
+                });
+
+                client_entity
+                    .insert(Cell {
+                        size: INITIAL_PLAYER_SIZE,
+                    })
+                    .insert(PhysicsBundle::moving_entity())
+                    .insert(Name::new("Player"))
+                    .insert(PlayerInput::default())
+                    .insert(Velocity::default())
+                    .insert(ActiveEvents::COLLISION_EVENTS)
+                    .insert(Collider::ball(INITIAL_PLAYER_SIZE / 2.0));
+                if let Some(team) = team {
+                    client_entity.insert(team);
+                }
+
+                if client_id == Some(id) {
+                    client_entity.insert(ControlledPlayer);
+                    // a new round respawns us without going through "Play again"
+                    if game_state.current() == &GameState::GameOver {
+                        game_state.set(GameState::InGame).unwrap();
+                    }
+                }
+
+                let player_info = PlayerInfo {
+                    server_entity: entity,
+                    client_entity: client_entity.id(),
+                };
+                lobby.players.insert(id, player_info);
+                network_mapping.0.insert(entity, client_entity.id());
+            }
+            ServerMessages::PlayerRemove { id } => {
+                debug!("Player {} disconnected.", id);
+                if let Some(PlayerInfo {
+                    server_entity,
+                    client_entity,
+                }) = lobby.players.remove(&id)
+                {
+                    commands.entity(client_entity).despawn();
+                    network_mapping.0.remove(&server_entity);
+
+                    if let Ok(current_player_id) = controlled_player.get_single() {
+                        if client_entity == current_player_id {
+                            game_state.set(GameState::GameOver).unwrap();
+                        }
+                    }
+                }
+            }
+            ServerMessages::PlayerKilled { killer, mass } => {
+                info!("Eaten by {} with mass {}.", killer, mass);
+                *game_over_info = GameOverInfo { killer, mass };
+            }
+            ServerMessages::PlayerEaten {
+                eater,
+                victim,
+                mass,
+            } => {
+                kill_feed.push(client_id, eater.as_ref(), &victim, mass);
+            }
+            ServerMessages::SpawnNpcCell {
+                entity,
+                translation,
+                size,
+            } => {
+                let [x, _y, z] = translation;
+                let mut npc_entity = commands.spawn(PbrBundle {
+                    mesh: meshes.add(Mesh::from(shape::Icosphere {
+                        radius: size,
+                        subdivisions: 4,
+                    })),
+                    material: materials.add(Color::rgb(x, z, size).into()),
+                    transform: Transform::from_translation(Vec3::new(x, -size / 2.0, z)),
+                    ..Default::default()
+                });
+
+                npc_entity
+                    .insert(Cell { size })
+                    .insert(Name::new("NPC"))
+                    .insert(Collider::ball(size / 2.0))
+                    .insert(ActiveEvents::COLLISION_EVENTS)
+                    .insert(PhysicsBundle::moving_entity())
+                    .insert(NpcCell);
+                network_mapping.0.insert(entity, npc_entity.id());
+            }
+            ServerMessages::DespawnEntity { entity } => {
+                if let Some(client_entity) = network_mapping.0.remove(&entity) {
+                    commands.entity(client_entity).despawn();
+                }
+            }
+            ServerMessages::UpdateEntityCell { entity, size } => {
+                if let Some(client_entity) = network_mapping.0.get(&entity) {
+                    commands.entity(*client_entity).insert(Cell { size });
+                }
+            }
+            ServerMessages::Leaderboard {
+                entries,
+                own_rank,
+                players,
+                teams,
+            } => {
+                *leaderboard = ClientLeaderboard {
+                    entries,
+                    own_rank,
+                    players,
+                    teams,
+                };
+            }
+            ServerMessages::Chat { id, name, text } => {
+                chat_log.push(ChatLine {
+                    name,
+                    text,
+                    notice: id.is_none(),
+                });
+            }
+            ServerMessages::RoundCountdown {
+                round: number,
+                remaining,
+            } => {
+                *round = ClientRound {
+                    round: number,
+                    remaining: Some(remaining),
+                    ..Default::default()
+                };
+            }
+            ServerMessages::RoundResults {
+                round: number,
+                results,
+                next_round_in,
+            } => {
+                *round = ClientRound {
+                    round: number,
+                    remaining: None,
+                    results,
+                    next_round_in,
+                };
+            }
+            ServerMessages::ConnectionRefused { reason } => {
+                warn!("Connection refused: {}", reason);
+                if let Some(session) = session.as_mut() {
+                    session.refused = true;
+                }
+                chat_log.push(ChatLine {
+                    name: "Server".to_string(),
+                    text: reason,
+                    notice: true,
+                });
+            }
+            ServerMessages::Profile { stats } => {
+                profile.stats = Some(stats);
+            }
+            ServerMessages::Session { token } => {
+                if let Some(session) = session.as_mut() {
+                    session.connection_data.session = token;
+                }
+            }
+        }
+    }
+
+    while let Some(message) = client.receive_message(ServerChannel::NetworkedEntities) {
+        let networked_entities: NetworkedEntities = bincode::deserialize(&message).unwrap();
+
+        for i in 0..networked_entities.entities.len() {
+            if let Some(entity) = network_mapping.0.get(&networked_entities.entities[i]) {
+                let translation = networked_entities.translations[i].into();
+                let scale: Vec3 = networked_entities.scalings[i].into();
+                let transform = Transform {
+                    translation,
+                    scale: Vec3::from(scale),
+                    ..Default::default()
+                };
+                commands.entity(*entity).insert(transform);
+            }
+        }
+    }
+}
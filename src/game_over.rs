@@ -0,0 +1,135 @@
+use bevy::{app::AppExit, prelude::*};
+
+use crate::{main_menu::spawn_button, GameState, PlayerCommand};
+
+/// Filled in by the client when the server tells us we were eaten.
+#[derive(Debug, Default, Resource)]
+pub struct GameOverInfo {
+    pub killer: String,
+    pub mass: f32,
+}
+
+#[derive(Component)]
+pub struct GameOverUIRoot;
+
+#[derive(Component)]
+pub struct PlayAgainButton;
+
+#[derive(Component)]
+pub struct GameOverQuitButton;
+
+pub struct GameOverPlugin;
+
+impl Plugin for GameOverPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameOverInfo>()
+            .add_system_set(
+                SystemSet::on_enter(GameState::GameOver).with_system(spawn_game_over_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(play_again_button_clicked)
+                    .with_system(quit_button_clicked),
+            )
+            // also left without a click when a new round respawns us
+            .add_system_set(
+                SystemSet::on_exit(GameState::GameOver).with_system(despawn_game_over_screen),
+            );
+    }
+}
+
+fn play_again_button_clicked(
+    interactions: Query<&Interaction, (With<PlayAgainButton>, Changed<Interaction>)>,
+    mut game_state: ResMut<State<GameState>>,
+    mut mouse_input: ResMut<Input<MouseButton>>,
+    mut player_commands: EventWriter<PlayerCommand>,
+) {
+    for interaction in &interactions {
+        if matches!(interaction, Interaction::Clicked) {
+            // the server keeps our connection open, we only need a new cell
+            player_commands.send(PlayerCommand::Respawn);
+            game_state.set(GameState::InGame).unwrap();
+            mouse_input.clear();
+        }
+    }
+}
+
+fn despawn_game_over_screen(
+    mut commands: Commands,
+    game_over_root: Query<Entity, With<GameOverUIRoot>>,
+) {
+    for root_entity in game_over_root.iter() {
+        commands.entity(root_entity).despawn_recursive();
+    }
+}
+
+fn quit_button_clicked(
+    interactions: Query<&Interaction, (With<GameOverQuitButton>, Changed<Interaction>)>,
+    mut exit: EventWriter<AppExit>,
+) {
+    for interaction in &interactions {
+        if matches!(interaction, Interaction::Clicked) {
+            exit.send(AppExit);
+        }
+    }
+}
+
+fn spawn_game_over_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    game_over_info: Res<GameOverInfo>,
+) {
+    let play_again_button = spawn_button(&mut commands, &asset_server, "Play again", Color::RED);
+    commands.entity(play_again_button).insert(PlayAgainButton);
+
+    let quit_button = spawn_button(&mut commands, &asset_server, "Quit", Color::BLUE);
+    commands.entity(quit_button).insert(GameOverQuitButton);
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(GameOverUIRoot)
+        .with_children(|commands| {
+            commands.spawn(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::Center,
+                    margin: UiRect::all(Val::Percent(2.0)),
+                    ..default()
+                },
+                text: Text::from_section(
+                    format!("You were eaten by {}", game_over_info.killer),
+                    TextStyle {
+                        font: asset_server.load("FiraSans-Bold.ttf"),
+                        font_size: 64.0,
+                        color: Color::BLACK,
+                    },
+                ),
+                ..default()
+            });
+            commands.spawn(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::Center,
+                    margin: UiRect::all(Val::Percent(1.0)),
+                    ..default()
+                },
+                text: Text::from_section(
+                    format!("Final mass: {:.1}", game_over_info.mass),
+                    TextStyle {
+                        font: asset_server.load("FiraMono-Medium.ttf"),
+                        font_size: 40.0,
+                        color: Color::BLACK,
+                    },
+                ),
+                ..default()
+            });
+        })
+        .add_child(play_again_button)
+        .add_child(quit_button);
+}
@@ -0,0 +1,149 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{
+    cells::{Cell, NpcCell},
+    ControlledPlayer, GameState, FIELD_SIZE,
+};
+
+pub const MINIMAP_SIZE: f32 = 180.0;
+// cells further than this from us are left off the minimap
+pub const MINIMAP_RANGE: f32 = 200.0;
+
+/// Stats for the current life of the controlled player, reset on every spawn.
+#[derive(Debug, Default, Resource)]
+pub struct HudStats {
+    pub spawned_at: f32,
+    pub peak_mass: f32,
+}
+
+impl HudStats {
+    /// Peak mass of this life, scaled to a whole number.
+    pub fn score(&self) -> u32 {
+        (self.peak_mass * 100.0).round() as u32
+    }
+}
+
+pub struct HudPlugin;
+
+impl Plugin for HudPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<HudStats>().add_system_set(
+            SystemSet::on_update(GameState::InGame)
+                .with_system(update_hud_stats)
+                .with_system(hud_window.after(update_hud_stats))
+                .with_system(minimap_window),
+        );
+    }
+}
+
+fn update_hud_stats(
+    time: Res<Time>,
+    mut stats: ResMut<HudStats>,
+    spawned: Query<&Cell, Added<ControlledPlayer>>,
+    controlled_player: Query<&Cell, With<ControlledPlayer>>,
+) {
+    if let Ok(cell) = spawned.get_single() {
+        *stats = HudStats {
+            spawned_at: time.elapsed_seconds(),
+            peak_mass: cell.size,
+        };
+    }
+
+    if let Ok(cell) = controlled_player.get_single() {
+        stats.peak_mass = stats.peak_mass.max(cell.size);
+    }
+}
+
+fn hud_window(
+    mut egui_context: ResMut<EguiContext>,
+    time: Res<Time>,
+    stats: Res<HudStats>,
+    controlled_player: Query<(&Transform, &Cell), With<ControlledPlayer>>,
+) {
+    let (transform, cell) = match controlled_player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    let alive = (time.elapsed_seconds() - stats.spawned_at).max(0.0) as u32;
+    egui::Window::new("Stats")
+        .anchor(egui::Align2::LEFT_TOP, [10.0, 10.0])
+        .resizable(false)
+        .collapsible(false)
+        .title_bar(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("hud_grid").show(ui, |ui| {
+                ui.label("Mass");
+                ui.label(format!("{:.1}", cell.size));
+                ui.end_row();
+                ui.label("Score");
+                ui.label(stats.score().to_string());
+                ui.end_row();
+                ui.label("Alive");
+                ui.label(format!("{}:{:02}", alive / 60, alive % 60));
+                ui.end_row();
+                ui.label("Position");
+                ui.label(format!(
+                    "{:.0}, {:.0}",
+                    transform.translation.x, transform.translation.z
+                ));
+                ui.end_row();
+            });
+        });
+}
+
+fn minimap_window(
+    mut egui_context: ResMut<EguiContext>,
+    controlled_player: Query<(Entity, &Transform, &Cell), With<ControlledPlayer>>,
+    cells: Query<(Entity, &Transform, &Cell, Option<&NpcCell>)>,
+) {
+    let (player_entity, player_transform, player_cell) = match controlled_player.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    egui::Window::new("Minimap")
+        .anchor(egui::Align2::LEFT_BOTTOM, [10.0, -10.0])
+        .resizable(false)
+        .collapsible(false)
+        .title_bar(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            let (response, painter) =
+                ui.allocate_painter(egui::vec2(MINIMAP_SIZE, MINIMAP_SIZE), egui::Sense::hover());
+            let rect = response.rect;
+            let to_map = |translation: Vec3| {
+                let x = (translation.x / FIELD_SIZE + 0.5) * rect.width();
+                let y = (translation.z / FIELD_SIZE + 0.5) * rect.height();
+                rect.min + egui::vec2(x, y)
+            };
+
+            painter.rect_filled(rect, 2.0, egui::Color32::from_black_alpha(160));
+            painter.rect_stroke(rect, 2.0, (1.0, egui::Color32::GRAY));
+
+            for (entity, transform, cell, npc) in cells.iter() {
+                if entity == player_entity {
+                    continue;
+                }
+                let distance = transform.translation.distance(player_transform.translation);
+                if distance > MINIMAP_RANGE {
+                    continue;
+                }
+                // only show food that could actually eat us
+                let color = if cell.size > player_cell.size {
+                    egui::Color32::RED
+                } else if npc.is_none() {
+                    egui::Color32::LIGHT_GRAY
+                } else {
+                    continue;
+                };
+                painter.circle_filled(to_map(transform.translation), 2.5, color);
+            }
+
+            painter.circle_filled(
+                to_map(player_transform.translation),
+                3.5,
+                egui::Color32::LIGHT_BLUE,
+            );
+        });
+}
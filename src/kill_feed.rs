@@ -0,0 +1,149 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::GameState;
+
+// seconds a kill feed line stays up, it fades out over the last second
+pub const KILL_FEED_DURATION: f32 = 5.0;
+pub const KILL_FEED_SIZE: usize = 5;
+// seconds a "you ate" or "you were eaten" notification stays up
+pub const NOTIFICATION_DURATION: f32 = 3.0;
+const FADE_DURATION: f32 = 1.0;
+
+/// A player named in a `ServerMessages::PlayerEaten`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerTag {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone)]
+struct FeedLine {
+    text: String,
+    // the local player ate or was eaten
+    own: bool,
+    age: f32,
+}
+
+/// Recent eats in the local player's arena, plus the latest one they were part of.
+#[derive(Debug, Default, Resource)]
+pub struct KillFeed {
+    lines: VecDeque<FeedLine>,
+    notification: Option<FeedLine>,
+}
+
+impl KillFeed {
+    /// Adds an eat to the feed, `client_id` is the local player when there is one.
+    pub fn push(
+        &mut self,
+        client_id: Option<u64>,
+        eater: Option<&PlayerTag>,
+        victim: &PlayerTag,
+        mass: f32,
+    ) {
+        let eater_name = eater.map_or("a cell", |eater| eater.name.as_str());
+        let ate = eater.is_some() && eater.map(|eater| eater.id) == client_id;
+        let eaten = client_id == Some(victim.id);
+
+        self.lines.push_back(FeedLine {
+            text: format!("{} ate {} ({:.1})", eater_name, victim.name, mass),
+            own: ate || eaten,
+            age: 0.0,
+        });
+        while self.lines.len() > KILL_FEED_SIZE {
+            self.lines.pop_front();
+        }
+
+        let notification = if eaten {
+            Some(format!("You were eaten by {}", eater_name))
+        } else if ate {
+            Some(format!("You ate {}", victim.name))
+        } else {
+            None
+        };
+        if let Some(text) = notification {
+            self.notification = Some(FeedLine {
+                text,
+                own: true,
+                age: 0.0,
+            });
+        }
+    }
+
+    pub fn tick(&mut self, delta: f32) {
+        for line in self.lines.iter_mut() {
+            line.age += delta;
+        }
+        self.lines.retain(|line| line.age < KILL_FEED_DURATION);
+        if let Some(notification) = self.notification.as_mut() {
+            notification.age += delta;
+            if notification.age >= NOTIFICATION_DURATION {
+                self.notification = None;
+            }
+        }
+    }
+}
+
+// fully opaque until the last FADE_DURATION seconds
+fn opacity(age: f32, duration: f32) -> f32 {
+    ((duration - age) / FADE_DURATION).clamp(0.0, 1.0)
+}
+
+pub struct KillFeedPlugin;
+
+impl Plugin for KillFeedPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<KillFeed>()
+            .add_system(tick_kill_feed)
+            .add_system_set(
+                SystemSet::on_update(GameState::InGame)
+                    .with_system(kill_feed_window.after(tick_kill_feed)),
+            )
+            // keeps the feed going behind the game over screen
+            .add_system_set(
+                SystemSet::on_update(GameState::GameOver)
+                    .with_system(kill_feed_window.after(tick_kill_feed)),
+            );
+    }
+}
+
+fn tick_kill_feed(time: Res<Time>, mut kill_feed: ResMut<KillFeed>) {
+    kill_feed.tick(time.delta_seconds());
+}
+
+fn kill_feed_window(mut egui_context: ResMut<EguiContext>, kill_feed: Res<KillFeed>) {
+    let ctx = egui_context.ctx_mut();
+    if !kill_feed.lines.is_empty() {
+        egui::Area::new("kill_feed")
+            .anchor(egui::Align2::RIGHT_CENTER, [-10.0, 0.0])
+            .interactable(false)
+            .show(ctx, |ui| {
+                for line in kill_feed.lines.iter() {
+                    let color = if line.own {
+                        egui::Color32::GOLD
+                    } else {
+                        egui::Color32::WHITE
+                    };
+                    let alpha = opacity(line.age, KILL_FEED_DURATION);
+                    ui.colored_label(color.linear_multiply(alpha), &line.text);
+                }
+            });
+    }
+
+    if let Some(notification) = &kill_feed.notification {
+        let alpha = opacity(notification.age, NOTIFICATION_DURATION);
+        egui::Area::new("kill_notification")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, -150.0])
+            .interactable(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    egui::RichText::new(&notification.text)
+                        .heading()
+                        .color(egui::Color32::GOLD.linear_multiply(alpha)),
+                );
+            });
+    }
+}
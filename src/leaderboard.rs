@@ -0,0 +1,167 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::Arena,
+    cells::Cell,
+    server::ServerSender,
+    settings::ServerSettings,
+    team::{team_masses, Team},
+    Game, Player, ServerChannel, ServerMessages,
+};
+
+pub const LEADERBOARD_SIZE: usize = 10;
+// how often the server sends the ranking, in seconds
+pub const LEADERBOARD_INTERVAL: f32 = 0.25;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+    pub id: u64,
+    pub name: String,
+    pub mass: f32,
+}
+
+/// Where the receiving client stands, sent even when outside the top entries.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LeaderboardRank {
+    pub rank: u32,
+    pub mass: f32,
+}
+
+/// Latest leaderboard received by the client.
+#[derive(Debug, Default, Resource)]
+pub struct ClientLeaderboard {
+    pub entries: Vec<LeaderboardEntry>,
+    pub own_rank: Option<LeaderboardRank>,
+    pub players: u32,
+    pub teams: Vec<f32>,
+}
+
+/// Sorts players by mass, biggest first. Ties keep a stable order by id.
+pub fn rank_players(players: impl Iterator<Item = LeaderboardEntry>) -> Vec<LeaderboardEntry> {
+    let mut ranking: Vec<LeaderboardEntry> = players.collect();
+    ranking.sort_by(|a, b| b.mass.total_cmp(&a.mass).then(a.id.cmp(&b.id)));
+    ranking
+}
+
+pub fn server_send_leaderboard(
+    time: Res<Time>,
+    mut game: ResMut<Game>,
+    mut server: ServerSender,
+    settings: Res<ServerSettings>,
+    players: Query<(&Player, &Cell, Option<&Team>, &Arena)>,
+) {
+    game.leaderboard_timer.tick(time.delta());
+    if !game.leaderboard_timer.just_finished() {
+        return;
+    }
+
+    let arenas: Vec<Arena> = server.arenas().iter().map(|(arena, _)| arena).collect();
+    for arena in arenas {
+        let in_arena = || {
+            players
+                .iter()
+                .filter(move |(_, _, _, player_arena)| **player_arena == arena)
+        };
+        let ranking = rank_players(in_arena().map(|(player, cell, _, _)| LeaderboardEntry {
+            id: player.id,
+            name: player.name.clone(),
+            mass: cell.size,
+        }));
+        let teams = team_masses(
+            in_arena().filter_map(|(_, cell, team, _)| team.map(|team| (*team, cell.size))),
+            settings.teams,
+        );
+        send_arena_leaderboard(&mut server, arena, &ranking, &teams);
+    }
+}
+
+fn send_arena_leaderboard(
+    server: &mut ServerSender,
+    arena: Arena,
+    ranking: &[LeaderboardEntry],
+    teams: &[f32],
+) {
+    let entries: Vec<LeaderboardEntry> = ranking.iter().take(LEADERBOARD_SIZE).cloned().collect();
+    let clients: Vec<u64> = server
+        .clients_id()
+        .into_iter()
+        .filter(|client_id| server.arenas().is_member(*client_id, arena))
+        .collect();
+
+    for client_id in clients {
+        let own_rank = ranking
+            .iter()
+            .position(|entry| entry.id == client_id)
+            .map(|index| LeaderboardRank {
+                rank: index as u32 + 1,
+                mass: ranking[index].mass,
+            });
+        let message = bincode::serialize(&ServerMessages::Leaderboard {
+            entries: entries.clone(),
+            own_rank,
+            players: ranking.len() as u32,
+            teams: teams.to_vec(),
+        })
+        .unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+    }
+}
+
+pub fn leaderboard_window(
+    mut egui_context: ResMut<EguiContext>,
+    leaderboard: Res<ClientLeaderboard>,
+) {
+    egui::Window::new("Leaderboard")
+        .anchor(egui::Align2::RIGHT_TOP, [-10.0, 10.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            let own_rank = leaderboard.own_rank.map(|own_rank| own_rank.rank as usize);
+
+            egui::Grid::new("leaderboard_grid").show(ui, |ui| {
+                for (index, entry) in leaderboard.entries.iter().enumerate() {
+                    let rank = index + 1;
+                    let color = if own_rank == Some(rank) {
+                        egui::Color32::YELLOW
+                    } else {
+                        egui::Color32::WHITE
+                    };
+                    ui.colored_label(color, format!("#{}", rank));
+                    ui.colored_label(color, &entry.name);
+                    ui.colored_label(color, format!("{:.1}", entry.mass));
+                    ui.end_row();
+                }
+
+                // we're not in the top entries, show where we are below them
+                if let Some(own_rank) = leaderboard.own_rank {
+                    if own_rank.rank as usize > leaderboard.entries.len() {
+                        ui.label("...");
+                        ui.end_row();
+                        ui.colored_label(egui::Color32::YELLOW, format!("#{}", own_rank.rank));
+                        ui.colored_label(egui::Color32::YELLOW, "You");
+                        ui.colored_label(egui::Color32::YELLOW, format!("{:.1}", own_rank.mass));
+                        ui.end_row();
+                    }
+                }
+            });
+
+            if !leaderboard.teams.is_empty() {
+                ui.separator();
+                egui::Grid::new("team_grid").show(ui, |ui| {
+                    for (index, mass) in leaderboard.teams.iter().enumerate() {
+                        let team = Team(index as u8);
+                        let [r, g, b, _] = team.color().as_rgba_u8();
+                        let color = egui::Color32::from_rgb(r, g, b);
+                        ui.colored_label(color, team.name());
+                        ui.colored_label(color, format!("{:.1}", mass));
+                        ui.end_row();
+                    }
+                });
+            }
+
+            ui.separator();
+            ui.label(format!("{} players", leaderboard.players));
+        });
+}
@@ -1,24 +1,57 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 
 use cells::Cell;
+use kill_feed::PlayerTag;
+use leaderboard::{LeaderboardEntry, LeaderboardRank};
+use profile::ProfileStats;
+use round::RoundResult;
 use simula_viz::{
     grid::{Grid, GridBundle},
     lines::{LineMesh, LinesMaterial},
 };
+use spectator::Spectator;
+use team::Team;
 
 use bevy_renet::renet::{
     ChannelConfig, ReliableChannelConfig, RenetConnectionConfig, UnreliableChannelConfig,
-    NETCODE_KEY_BYTES,
+    NETCODE_KEY_BYTES, NETCODE_USER_DATA_BYTES,
 };
 use serde::{Deserialize, Serialize};
 use smooth_bevy_cameras::{LookTransform, LookTransformBundle, Smoother};
 
+pub mod access;
+pub mod admin;
+pub mod arena;
+pub mod bot;
 pub mod cells;
+pub mod chat;
+pub mod client;
+pub mod game_over;
+pub mod hud;
+pub mod kill_feed;
+pub mod leaderboard;
+pub mod logging;
 pub mod main_menu;
+pub mod metrics;
+pub mod net_debug;
 pub mod physics;
 pub mod player;
+pub mod profile;
+pub mod replay;
+pub mod rng;
+pub mod round;
+pub mod server;
+pub mod session;
+pub mod settings;
+pub mod spawn;
+pub mod spectator;
+pub mod team;
+pub mod validation;
 
 pub const FIELD_SIZE: f32 = 900.0;
 
@@ -28,16 +61,28 @@ pub const PROTOCOL_ID: u64 = 7;
 pub const WINDOW_HEIGHT: f32 = 720.0;
 pub const WINDOW_WIDTH: f32 = 1280.0;
 
+pub const MAX_PLAYER_NAME_LENGTH: usize = 32;
+// in chars, kept short so a full name and room code fit in the netcode user data
+pub const MAX_ROOM_CODE_LENGTH: usize = 16;
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub enum GameState {
     MainMenu,
     InGame,
     GameOver,
+    // lifetime stats, opened from the main menu
+    Stats,
 }
 
 #[derive(Debug, Default, Resource)]
 pub struct ServerLobby {
     pub players: HashMap<u64, Entity>,
+    // names outlive the player's cell so we can respawn them after being eaten
+    pub names: HashMap<u64, String>,
+    // connected clients watching without a cell
+    pub spectators: HashSet<u64>,
+    // kept across respawns, empty in free-for-all
+    pub teams: HashMap<u64, Team>,
 }
 
 #[derive(Resource)]
@@ -47,7 +92,7 @@ pub struct GameAssets {
 
 #[derive(Resource)]
 pub struct Game {
-    pub cell_spawn_timer: Timer,
+    pub leaderboard_timer: Timer,
 }
 
 #[derive(Reflect, Component, Default)]
@@ -61,6 +106,56 @@ pub struct FollowCamera {
 #[derive(Debug, Component)]
 pub struct Player {
     pub id: u64,
+    pub name: String,
+}
+
+/// Sent by the client in the netcode `user_data` when connecting.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ConnectionData {
+    pub name: String,
+    // watch the match without getting a cell
+    pub spectator: bool,
+    // arena to join, the emptiest one when it matches no room
+    pub room: String,
+    // stats are kept under this key when the server has profiles on, 0 for none
+    pub profile: u64,
+    // token from `ServerMessages::Session` when reconnecting, 0 for a new session
+    pub session: u64,
+}
+
+impl ConnectionData {
+    /// Caps the name and room code, at 4 bytes a char the rest always fits.
+    pub fn to_user_data(&self) -> [u8; NETCODE_USER_DATA_BYTES] {
+        let mut data = self.clone();
+        data.cap_lengths();
+
+        let bytes = bincode::serialize(&data).unwrap();
+        let mut user_data = [0u8; NETCODE_USER_DATA_BYTES];
+        user_data[..bytes.len()].copy_from_slice(&bytes);
+        user_data
+    }
+
+    pub fn from_user_data(user_data: &[u8; NETCODE_USER_DATA_BYTES]) -> Self {
+        let mut data: Self = bincode::deserialize(&user_data[..]).unwrap_or_default();
+        // the client is trusted with nothing
+        data.cap_lengths();
+        data
+    }
+
+    fn cap_lengths(&mut self) {
+        self.name = self.name.chars().take(MAX_PLAYER_NAME_LENGTH).collect();
+        self.room = self.room.chars().take(MAX_ROOM_CODE_LENGTH).collect();
+    }
+
+    /// The name to show for `client_id`, falling back to a generated one.
+    pub fn player_name(&self, client_id: u64) -> String {
+        let name = self.name.trim();
+        if name.is_empty() {
+            format!("Player {}", client_id % 10000)
+        } else {
+            name.chars().take(MAX_PLAYER_NAME_LENGTH).collect()
+        }
+    }
 }
 
 #[derive(Component)]
@@ -78,11 +173,16 @@ pub struct PlayerInput {
 #[derive(Debug, Serialize, Deserialize, Component)]
 pub enum PlayerCommand {
     BasicAttack { cast_at: Vec3 },
+    // ask for a new cell after being eaten, keeping the same connection
+    Respawn,
+    // quitting on purpose, the server removes the cell and disconnects instead of keeping it
+    Leave,
 }
 
 pub enum ClientChannel {
     Input,
     Command,
+    Chat,
 }
 
 pub enum ServerChannel {
@@ -96,10 +196,22 @@ pub enum ServerMessages {
         entity: Entity,
         id: u64,
         translation: [f32; 3],
+        team: Option<Team>,
     },
     PlayerRemove {
         id: u64,
     },
+    // sent only to the player that was eaten, before the PlayerRemove broadcast
+    PlayerKilled {
+        killer: String,
+        mass: f32,
+    },
+    // sent to the whole arena for the kill feed, `eater` is None when a non-player cell did it
+    PlayerEaten {
+        eater: Option<PlayerTag>,
+        victim: PlayerTag,
+        mass: f32,
+    },
     SpawnNpcCell {
         entity: Entity,
         translation: [f32; 3],
@@ -112,6 +224,64 @@ pub enum ServerMessages {
         entity: Entity,
         size: f32,
     },
+    // top players plus the receiving client's own rank
+    Leaderboard {
+        entries: Vec<LeaderboardEntry>,
+        own_rank: Option<LeaderboardRank>,
+        players: u32,
+        // total mass per team, empty in free-for-all
+        teams: Vec<f32>,
+    },
+    // already filtered by the server, `id` is None for server notices
+    Chat {
+        id: Option<u64>,
+        name: String,
+        text: String,
+    },
+    // seconds left in the current round, sent about once a second
+    RoundCountdown {
+        round: u32,
+        remaining: f32,
+    },
+    RoundResults {
+        round: u32,
+        results: Vec<RoundResult>,
+        next_round_in: f32,
+    },
+    // sent right before the server disconnects a client it won't let play
+    ConnectionRefused {
+        reason: String,
+    },
+    // the receiving client's lifetime stats, on connect and after every game
+    Profile {
+        stats: ProfileStats,
+    },
+    // sent on connect, reconnecting with it within the grace period gives the cell back
+    Session {
+        token: u64,
+    },
+}
+
+impl ServerMessages {
+    /// Variant name, used to break down message statistics.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            ServerMessages::PlayerCreate { .. } => "PlayerCreate",
+            ServerMessages::PlayerRemove { .. } => "PlayerRemove",
+            ServerMessages::PlayerKilled { .. } => "PlayerKilled",
+            ServerMessages::PlayerEaten { .. } => "PlayerEaten",
+            ServerMessages::SpawnNpcCell { .. } => "SpawnNpcCell",
+            ServerMessages::DespawnEntity { .. } => "DespawnEntity",
+            ServerMessages::UpdateEntityCell { .. } => "UpdateEntityCell",
+            ServerMessages::Leaderboard { .. } => "Leaderboard",
+            ServerMessages::Chat { .. } => "Chat",
+            ServerMessages::RoundCountdown { .. } => "RoundCountdown",
+            ServerMessages::RoundResults { .. } => "RoundResults",
+            ServerMessages::ConnectionRefused { .. } => "ConnectionRefused",
+            ServerMessages::Profile { .. } => "Profile",
+            ServerMessages::Session { .. } => "Session",
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Default)]
@@ -126,6 +296,7 @@ impl From<ClientChannel> for u8 {
         match channel_id {
             ClientChannel::Command => 0,
             ClientChannel::Input => 1,
+            ClientChannel::Chat => 2,
         }
     }
 }
@@ -145,6 +316,12 @@ impl ClientChannel {
                 ..Default::default()
             }
             .into(),
+            ReliableChannelConfig {
+                channel_id: Self::Chat.into(),
+                message_resend_time: Duration::from_millis(200),
+                ..Default::default()
+            }
+            .into(),
         ]
     }
 }
@@ -159,6 +336,15 @@ impl From<ServerChannel> for u8 {
 }
 
 impl ServerChannel {
+    /// Name of a channel id, for stats and debug output.
+    pub fn name(channel_id: u8) -> &'static str {
+        match channel_id {
+            0 => "NetworkedEntities",
+            1 => "ServerMessages",
+            _ => "unknown",
+        }
+    }
+
     pub fn channels_config() -> Vec<ChannelConfig> {
         vec![
             UnreliableChannelConfig {
@@ -255,12 +441,19 @@ pub fn setup_camera(mut commands: Commands) {
         });
 }
 
+/// Follows the controlled player, or whoever a spectator is watching.
 pub fn camera_follow(
     mut camera_query: Query<&mut LookTransform, (With<Camera>, Without<ControlledPlayer>)>,
     player_query: Query<(&Transform, &Cell), With<ControlledPlayer>>,
+    cells: Query<(&Transform, &Cell)>,
+    spectator: Option<Res<Spectator>>,
 ) {
     let mut cam_transform = camera_query.single_mut();
-    if let Ok((player_transform, cell)) = player_query.get_single() {
+    let followed = player_query.get_single().ok().or_else(|| {
+        let (_, entity) = spectator?.following?;
+        cells.get(entity).ok()
+    });
+    if let Some((player_transform, cell)) = followed {
         cam_transform.eye.x = player_transform.translation.x;
         cam_transform.eye.z = player_transform.translation.z + 16.5 + cell.size;
         cam_transform.eye.y = player_transform.translation.y + 22.0 + (3.0 * cell.size);
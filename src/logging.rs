@@ -0,0 +1,67 @@
+use tracing_subscriber::EnvFilter;
+
+// wgpu and naga are very chatty at info
+pub const DEFAULT_LOG_FILTER: &str = "info,wgpu=error,naga=warn";
+
+/// How log lines are filtered and printed, the same for every binary.
+///
+/// usage: [--log <filter>] [--log-json]
+///
+/// The filter takes `RUST_LOG` directives, for example `--log warn,cagario::server=debug`
+/// to only see the server's connection handling in detail.
+#[derive(Debug, Clone)]
+pub struct LogSettings {
+    pub filter: String,
+    // one JSON object per line, with the client span as a field
+    pub json: bool,
+}
+
+impl Default for LogSettings {
+    fn default() -> Self {
+        Self {
+            filter: DEFAULT_LOG_FILTER.to_string(),
+            json: false,
+        }
+    }
+}
+
+impl LogSettings {
+    /// Handles `--log <filter>` and `--log-json`, returns false for anything else.
+    pub fn parse_arg(&mut self, arg: &str, args: &mut impl Iterator<Item = String>) -> bool {
+        match arg {
+            "--log" => {
+                if let Some(filter) = args.next() {
+                    self.filter = filter;
+                }
+                true
+            }
+            "--log-json" => {
+                self.json = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Installs the global subscriber. Takes the place of bevy's `LogPlugin`, which has
+/// to be disabled since only one subscriber can be set.
+pub fn init_logging(settings: &LogSettings) {
+    let filter = EnvFilter::try_new(&settings.filter).unwrap_or_else(|e| {
+        eprintln!("Invalid log filter {}: {}", settings.filter, e);
+        EnvFilter::new(DEFAULT_LOG_FILTER)
+    });
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    let result = if settings.json {
+        builder
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init()
+    } else {
+        builder.try_init()
+    };
+    if let Err(e) = result {
+        eprintln!("Failed to set up logging: {}", e);
+    }
+}
@@ -1,6 +1,6 @@
 use bevy::{app::AppExit, prelude::*};
 
-use crate::GameState;
+use crate::{profile::ClientProfile, GameState};
 
 #[derive(Component)]
 pub struct MenuUIRoot;
@@ -8,9 +8,21 @@ pub struct MenuUIRoot;
 #[derive(Component)]
 pub struct StartButton;
 
+#[derive(Component)]
+pub struct StatsButton;
+
 #[derive(Component)]
 pub struct QuitButton;
 
+#[derive(Component)]
+pub struct StatsUIRoot;
+
+#[derive(Component)]
+pub struct StatsText;
+
+#[derive(Component)]
+pub struct StatsBackButton;
+
 pub struct MainMenuPlugin;
 
 impl Plugin for MainMenuPlugin {
@@ -20,8 +32,16 @@ impl Plugin for MainMenuPlugin {
             .add_system_set(
                 SystemSet::on_update(GameState::MainMenu)
                     .with_system(start_button_clicked)
+                    .with_system(stats_button_clicked)
                     .with_system(quit_button_clicked),
-            );
+            )
+            .add_system_set(SystemSet::on_enter(GameState::Stats).with_system(spawn_stats_screen))
+            .add_system_set(
+                SystemSet::on_update(GameState::Stats)
+                    .with_system(update_stats_text)
+                    .with_system(stats_back_button_clicked),
+            )
+            .add_system_set(SystemSet::on_exit(GameState::Stats).with_system(despawn_stats_screen));
     }
 }
 
@@ -43,6 +63,33 @@ fn start_button_clicked(
     }
 }
 
+fn stats_button_clicked(
+    mut commands: Commands,
+    interactions: Query<&Interaction, (With<StatsButton>, Changed<Interaction>)>,
+    menu_root: Query<Entity, With<MenuUIRoot>>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    for interaction in &interactions {
+        if matches!(interaction, Interaction::Clicked) {
+            let root_entity = menu_root.single();
+            commands.entity(root_entity).despawn_recursive();
+
+            game_state.set(GameState::Stats).unwrap();
+        }
+    }
+}
+
+fn stats_back_button_clicked(
+    interactions: Query<&Interaction, (With<StatsBackButton>, Changed<Interaction>)>,
+    mut game_state: ResMut<State<GameState>>,
+) {
+    for interaction in &interactions {
+        if matches!(interaction, Interaction::Clicked) {
+            game_state.set(GameState::MainMenu).unwrap();
+        }
+    }
+}
+
 fn quit_button_clicked(
     interactions: Query<&Interaction, (With<QuitButton>, Changed<Interaction>)>,
     mut exit: EventWriter<AppExit>,
@@ -58,6 +105,9 @@ fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
     let start_button = spawn_button(&mut commands, &asset_server, "Start Game", Color::RED);
     commands.entity(start_button).insert(StartButton);
 
+    let stats_button = spawn_button(&mut commands, &asset_server, "Stats", Color::GREEN);
+    commands.entity(stats_button).insert(StatsButton);
+
     let quit_button = spawn_button(&mut commands, &asset_server, "Quit", Color::BLUE);
     commands.entity(quit_button).insert(QuitButton);
 
@@ -91,10 +141,108 @@ fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
             });
         })
         .add_child(start_button)
+        .add_child(stats_button)
         .add_child(quit_button);
 }
 
-fn spawn_button(
+fn stats_lines(profile: &ClientProfile) -> String {
+    let stats = match &profile.stats {
+        Some(stats) => stats,
+        None => return "This server doesn't keep player stats.".to_string(),
+    };
+    let seconds = stats.time_alive as u32;
+    let mut lines = format!(
+        "Games played: {}\nBest mass: {:.1}\nKills: {}\nTime alive: {}:{:02}:{:02}",
+        stats.games_played,
+        stats.best_mass,
+        stats.kills,
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60,
+    );
+    // only games played on a team have a colour of their own
+    if let Some(color) = &stats.favorite_color {
+        lines.push_str("\nFavourite colour: ");
+        lines.push_str(color);
+    }
+    lines
+}
+
+fn spawn_stats_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    profile: Res<ClientProfile>,
+) {
+    let back_button = spawn_button(&mut commands, &asset_server, "Back", Color::BLUE);
+    commands.entity(back_button).insert(StatsBackButton);
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                justify_content: JustifyContent::Center,
+                flex_direction: FlexDirection::Column,
+                ..default()
+            },
+            ..default()
+        })
+        .insert(StatsUIRoot)
+        .with_children(|commands| {
+            commands.spawn(TextBundle {
+                style: Style {
+                    align_self: AlignSelf::Center,
+                    margin: UiRect::all(Val::Percent(2.0)),
+                    ..default()
+                },
+                text: Text::from_section(
+                    "Stats",
+                    TextStyle {
+                        font: asset_server.load("FiraSans-Bold.ttf"),
+                        font_size: 64.0,
+                        color: Color::BLACK,
+                    },
+                ),
+                ..default()
+            });
+            commands
+                .spawn(TextBundle {
+                    style: Style {
+                        align_self: AlignSelf::Center,
+                        margin: UiRect::all(Val::Percent(1.0)),
+                        ..default()
+                    },
+                    text: Text::from_section(
+                        stats_lines(&profile),
+                        TextStyle {
+                            font: asset_server.load("FiraMono-Medium.ttf"),
+                            font_size: 40.0,
+                            color: Color::BLACK,
+                        },
+                    ),
+                    ..default()
+                })
+                .insert(StatsText);
+        })
+        .add_child(back_button);
+}
+
+// the stats may only arrive after the screen was opened
+fn update_stats_text(profile: Res<ClientProfile>, mut texts: Query<&mut Text, With<StatsText>>) {
+    if !profile.is_changed() {
+        return;
+    }
+    for mut text in texts.iter_mut() {
+        text.sections[0].value = stats_lines(&profile);
+    }
+}
+
+fn despawn_stats_screen(mut commands: Commands, stats_root: Query<Entity, With<StatsUIRoot>>) {
+    for root_entity in stats_root.iter() {
+        commands.entity(root_entity).despawn_recursive();
+    }
+}
+
+pub(crate) fn spawn_button(
     commands: &mut Commands,
     asset_server: &AssetServer,
     text: &str,
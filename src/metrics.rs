@@ -0,0 +1,365 @@
+use std::{
+    collections::VecDeque,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bevy::{prelude::*, utils::HashMap};
+use bevy_renet::renet::RenetServer;
+
+use crate::{
+    bot::Bot,
+    cells::NpcCell,
+    physics::{FoodEaten, PlayerEaten},
+    Player, ServerChannel, ServerLobby,
+};
+
+// how often the scraped text is rebuilt, in seconds
+pub const METRICS_INTERVAL: f32 = 1.0;
+// window for the eat rate, in seconds
+pub const EAT_RATE_WINDOW: f64 = 60.0;
+// a scraper that doesn't send its request or read the answer in time is dropped
+pub const METRICS_REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ChannelCounters {
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// Eats since startup and within the last `EAT_RATE_WINDOW`.
+#[derive(Debug, Default, Clone)]
+struct EatCounter {
+    total: u64,
+    // elapsed seconds of every eat within the window
+    recent: VecDeque<f64>,
+}
+
+impl EatCounter {
+    fn count(&mut self, now: f64) {
+        self.total += 1;
+        self.recent.push_back(now);
+    }
+
+    fn forget_before(&mut self, now: f64) {
+        while let Some(eaten_at) = self.recent.front() {
+            if now - eaten_at <= EAT_RATE_WINDOW {
+                break;
+            }
+            self.recent.pop_front();
+        }
+    }
+}
+
+/// Everything a metrics page shows, gathered once per `METRICS_INTERVAL`.
+#[derive(Debug, Default, Clone)]
+pub struct MetricsSample {
+    pub clients: usize,
+    pub spectators: usize,
+    pub players: usize,
+    pub bots: usize,
+    pub food: usize,
+    pub tick_seconds: f64,
+    // sorted by channel id
+    pub channels: Vec<(u8, ChannelCounters)>,
+    // client id, RTT in milliseconds and packet loss
+    pub network: Vec<(u64, f32, f32)>,
+    pub players_eaten: u64,
+    pub food_eaten: u64,
+    pub players_eaten_per_minute: usize,
+    pub food_eaten_per_minute: usize,
+}
+
+/// Server metrics served in the Prometheus text format on a local HTTP port.
+///
+/// Only inserted when `--metrics` is passed. The listener runs on its own thread
+/// and answers with the text last built by `publish_metrics`.
+#[derive(Resource)]
+pub struct ServerMetrics {
+    page: Arc<Mutex<String>>,
+    publish_timer: Timer,
+    channels: HashMap<u8, ChannelCounters>,
+    tick_started: Option<Instant>,
+    tick_seconds: f64,
+    players_eaten: EatCounter,
+    food_eaten: EatCounter,
+}
+
+impl ServerMetrics {
+    pub fn serve(addr: SocketAddr) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let page = Arc::new(Mutex::new(String::new()));
+        let served = page.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                // answered one at a time, the timeouts keep an idle connection from holding up the rest
+                let timeouts = stream
+                    .set_read_timeout(Some(METRICS_REQUEST_TIMEOUT))
+                    .and_then(|_| stream.set_write_timeout(Some(METRICS_REQUEST_TIMEOUT)));
+                if let Err(e) = timeouts.and_then(|_| respond(stream, &served)) {
+                    warn!("Failed to answer a metrics request: {}", e);
+                }
+            }
+        });
+
+        Ok(Self {
+            page,
+            publish_timer: Timer::from_seconds(METRICS_INTERVAL, TimerMode::Repeating),
+            channels: HashMap::default(),
+            tick_started: None,
+            tick_seconds: 0.0,
+            players_eaten: EatCounter::default(),
+            food_eaten: EatCounter::default(),
+        })
+    }
+
+    /// Counts one payload on `channel` for each of its recipients.
+    pub fn count_sent(&mut self, channel: u8, bytes: usize, recipients: usize) {
+        let counters = self.channels.entry(channel).or_default();
+        counters.messages += recipients as u64;
+        counters.bytes += (bytes * recipients) as u64;
+    }
+}
+
+fn respond(stream: TcpStream, page: &Mutex<String>) -> io::Result<()> {
+    let mut reader = BufReader::new(stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // the rest of the request doesn't matter, the connection is closed after one answer
+    let path = request_line.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status, body) = if path == "/metrics" {
+        ("200 OK", page.lock().unwrap().clone())
+    } else {
+        ("404 Not Found", "try /metrics\n".to_string())
+    };
+    let mut stream = reader.into_inner();
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn metric_header(page: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(page, "# HELP {} {}", name, help);
+    let _ = writeln!(page, "# TYPE {} {}", name, kind);
+}
+
+pub fn begin_metrics_tick(metrics: Option<ResMut<ServerMetrics>>) {
+    if let Some(mut metrics) = metrics {
+        metrics.tick_started = Some(Instant::now());
+    }
+}
+
+pub fn end_metrics_tick(metrics: Option<ResMut<ServerMetrics>>) {
+    if let Some(mut metrics) = metrics {
+        if let Some(started) = metrics.tick_started.take() {
+            metrics.tick_seconds = started.elapsed().as_secs_f64();
+        }
+    }
+}
+
+pub fn count_eats(
+    time: Res<Time>,
+    metrics: Option<ResMut<ServerMetrics>>,
+    mut player_eaten: EventReader<PlayerEaten>,
+    mut food_eaten: EventReader<FoodEaten>,
+) {
+    let mut metrics = match metrics {
+        Some(metrics) => metrics,
+        None => return,
+    };
+    let now = time.elapsed_seconds_f64();
+    for _ in player_eaten.iter() {
+        metrics.players_eaten.count(now);
+    }
+    for _ in food_eaten.iter() {
+        metrics.food_eaten.count(now);
+    }
+    metrics.players_eaten.forget_before(now);
+    metrics.food_eaten.forget_before(now);
+}
+
+#[allow(clippy::type_complexity)]
+pub fn publish_metrics(
+    time: Res<Time>,
+    metrics: Option<ResMut<ServerMetrics>>,
+    server: Res<RenetServer>,
+    lobby: Res<ServerLobby>,
+    players: Query<Option<&Bot>, With<Player>>,
+    food: Query<(), With<NpcCell>>,
+) {
+    let mut metrics = match metrics {
+        Some(metrics) => metrics,
+        None => return,
+    };
+    if !metrics.publish_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let clients = server.clients_id();
+    let bots = players.iter().filter(|bot| bot.is_some()).count();
+    let mut channels: Vec<(u8, ChannelCounters)> =
+        metrics.channels.iter().map(|(k, v)| (*k, *v)).collect();
+    channels.sort_by_key(|(channel, _)| *channel);
+    let network = clients
+        .iter()
+        .filter_map(|id| {
+            server
+                .network_info(*id)
+                .map(|info| (*id, info.rtt, info.packet_loss))
+        })
+        .collect();
+
+    let page = render_metrics(&MetricsSample {
+        clients: clients.len(),
+        spectators: lobby.spectators.len(),
+        players: players.iter().count() - bots,
+        bots,
+        food: food.iter().count(),
+        tick_seconds: metrics.tick_seconds,
+        channels,
+        network,
+        players_eaten: metrics.players_eaten.total,
+        food_eaten: metrics.food_eaten.total,
+        players_eaten_per_minute: metrics.players_eaten.recent.len(),
+        food_eaten_per_minute: metrics.food_eaten.recent.len(),
+    });
+    *metrics.page.lock().unwrap() = page;
+}
+
+/// Builds the Prometheus text format page for `sample`.
+pub fn render_metrics(sample: &MetricsSample) -> String {
+    let mut page = String::new();
+
+    metric_header(&mut page, "cagario_clients", "gauge", "Connected clients.");
+    let _ = writeln!(page, "cagario_clients {}", sample.clients);
+    metric_header(
+        &mut page,
+        "cagario_spectators",
+        "gauge",
+        "Connected clients watching without a cell.",
+    );
+    let _ = writeln!(page, "cagario_spectators {}", sample.spectators);
+
+    metric_header(
+        &mut page,
+        "cagario_cells",
+        "gauge",
+        "Cells in every arena by kind.",
+    );
+    let _ = writeln!(page, "cagario_cells{{kind=\"player\"}} {}", sample.players);
+    let _ = writeln!(page, "cagario_cells{{kind=\"bot\"}} {}", sample.bots);
+    let _ = writeln!(page, "cagario_cells{{kind=\"food\"}} {}", sample.food);
+
+    metric_header(
+        &mut page,
+        "cagario_tick_seconds",
+        "gauge",
+        "Time the last frame took to update.",
+    );
+    let _ = writeln!(page, "cagario_tick_seconds {:.6}", sample.tick_seconds);
+
+    metric_header(
+        &mut page,
+        "cagario_messages_sent_total",
+        "counter",
+        "Messages sent by channel, once per recipient.",
+    );
+    for (channel, counters) in sample.channels.iter() {
+        let _ = writeln!(
+            page,
+            "cagario_messages_sent_total{{channel=\"{}\"}} {}",
+            ServerChannel::name(*channel),
+            counters.messages
+        );
+    }
+    metric_header(
+        &mut page,
+        "cagario_bytes_sent_total",
+        "counter",
+        "Payload bytes sent by channel, once per recipient.",
+    );
+    for (channel, counters) in sample.channels.iter() {
+        let _ = writeln!(
+            page,
+            "cagario_bytes_sent_total{{channel=\"{}\"}} {}",
+            ServerChannel::name(*channel),
+            counters.bytes
+        );
+    }
+
+    metric_header(
+        &mut page,
+        "cagario_client_rtt_milliseconds",
+        "gauge",
+        "Round trip time per client.",
+    );
+    for (id, rtt, _) in sample.network.iter() {
+        let _ = writeln!(
+            page,
+            "cagario_client_rtt_milliseconds{{client=\"{}\"}} {}",
+            id, rtt
+        );
+    }
+    metric_header(
+        &mut page,
+        "cagario_client_packet_loss",
+        "gauge",
+        "Share of packets lost per client.",
+    );
+    for (id, _, packet_loss) in sample.network.iter() {
+        let _ = writeln!(
+            page,
+            "cagario_client_packet_loss{{client=\"{}\"}} {}",
+            id, packet_loss
+        );
+    }
+
+    metric_header(
+        &mut page,
+        "cagario_eats_total",
+        "counter",
+        "Cells eaten by players since startup, by kind of cell eaten.",
+    );
+    let _ = writeln!(
+        page,
+        "cagario_eats_total{{kind=\"player\"}} {}",
+        sample.players_eaten
+    );
+    let _ = writeln!(
+        page,
+        "cagario_eats_total{{kind=\"food\"}} {}",
+        sample.food_eaten
+    );
+    metric_header(
+        &mut page,
+        "cagario_eats_per_minute",
+        "gauge",
+        "Cells eaten by players during the last minute, by kind of cell eaten.",
+    );
+    let _ = writeln!(
+        page,
+        "cagario_eats_per_minute{{kind=\"player\"}} {}",
+        sample.players_eaten_per_minute
+    );
+    let _ = writeln!(
+        page,
+        "cagario_eats_per_minute{{kind=\"food\"}} {}",
+        sample.food_eaten_per_minute
+    );
+
+    page
+}
@@ -0,0 +1,179 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContext};
+use bevy_renet::renet::{RenetClient, RenetServer, ServerEvent};
+use renet_visualizer::{RenetClientVisualizer, RenetServerVisualizer, RenetVisualizerStyle};
+
+use crate::{ServerChannel, ServerMessages};
+
+// samples kept by the renet graphs
+pub const VISUALIZER_SAMPLES: usize = 200;
+pub const NETWORK_DEBUG_KEY: KeyCode = KeyCode::F1;
+
+#[derive(Debug, Default, Clone)]
+struct MessageCounts {
+    channel_bytes: HashMap<u8, u64>,
+    variants: HashMap<&'static str, u64>,
+}
+
+/// Bytes by channel and messages by `ServerMessages` variant, over the last second.
+///
+/// Variants are only counted while the overlay is open, since that means decoding
+/// every payload a second time.
+#[derive(Debug, Default)]
+pub struct MessageStats {
+    elapsed: f32,
+    current: MessageCounts,
+    last_second: MessageCounts,
+}
+
+impl MessageStats {
+    pub fn record(&mut self, channel: u8, payload: &[u8], count: usize, decode: bool) {
+        *self.current.channel_bytes.entry(channel).or_default() += (payload.len() * count) as u64;
+        if decode && channel == u8::from(ServerChannel::ServerMessages) {
+            if let Ok(message) = bincode::deserialize::<ServerMessages>(payload) {
+                *self.current.variants.entry(message.kind()).or_default() += count as u64;
+            }
+        }
+    }
+
+    pub fn tick(&mut self, delta: f32) {
+        self.elapsed += delta;
+        if self.elapsed >= 1.0 {
+            self.elapsed = 0.0;
+            self.last_second = std::mem::take(&mut self.current);
+        }
+    }
+
+    pub fn show(&self, ui: &mut egui::Ui) {
+        let mut channels: Vec<(u8, u64)> = self
+            .last_second
+            .channel_bytes
+            .iter()
+            .map(|(channel, bytes)| (*channel, *bytes))
+            .collect();
+        channels.sort_unstable();
+        let mut variants: Vec<(&'static str, u64)> = self
+            .last_second
+            .variants
+            .iter()
+            .map(|(kind, count)| (*kind, *count))
+            .collect();
+        variants.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+
+        egui::Grid::new("channel_bandwidth_grid").show(ui, |ui| {
+            ui.strong("Channel");
+            ui.strong("kbps");
+            ui.end_row();
+            for (channel, bytes) in channels {
+                ui.label(ServerChannel::name(channel));
+                ui.label(format!("{:.1}", bytes as f32 * 8.0 / 1000.0));
+                ui.end_row();
+            }
+        });
+        ui.separator();
+        egui::Grid::new("message_variant_grid").show(ui, |ui| {
+            ui.strong("Message");
+            ui.strong("per second");
+            ui.end_row();
+            for (kind, count) in variants {
+                ui.label(kind);
+                ui.label(format!("{}", count));
+                ui.end_row();
+            }
+        });
+    }
+}
+
+/// Server side network overlay: renet's per-client graphs plus what we send.
+#[derive(Resource)]
+pub struct ServerNetworkDebug {
+    pub visible: bool,
+    visualizer: RenetServerVisualizer<VISUALIZER_SAMPLES>,
+    pub stats: MessageStats,
+}
+
+impl Default for ServerNetworkDebug {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            visualizer: RenetServerVisualizer::new(RenetVisualizerStyle::default()),
+            stats: MessageStats::default(),
+        }
+    }
+}
+
+/// Client side network overlay for the client's own link.
+#[derive(Resource)]
+pub struct ClientNetworkDebug {
+    pub visible: bool,
+    visualizer: RenetClientVisualizer<VISUALIZER_SAMPLES>,
+    pub stats: MessageStats,
+}
+
+impl Default for ClientNetworkDebug {
+    fn default() -> Self {
+        Self {
+            visible: false,
+            visualizer: RenetClientVisualizer::new(RenetVisualizerStyle::default()),
+            stats: MessageStats::default(),
+        }
+    }
+}
+
+pub fn track_debug_clients(
+    mut server_events: EventReader<ServerEvent>,
+    mut debug: ResMut<ServerNetworkDebug>,
+) {
+    for event in server_events.iter() {
+        match event {
+            ServerEvent::ClientConnected(id, _) => debug.visualizer.add_client(*id),
+            ServerEvent::ClientDisconnected(id) => debug.visualizer.remove_client(*id),
+        }
+    }
+}
+
+/// F1 toggles the overlay.
+pub fn server_network_debug(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut debug: ResMut<ServerNetworkDebug>,
+    server: Res<RenetServer>,
+) {
+    if keyboard_input.just_pressed(NETWORK_DEBUG_KEY) {
+        debug.visible = !debug.visible;
+    }
+    debug.visualizer.update(&server);
+    debug.stats.tick(time.delta_seconds());
+    if !debug.visible {
+        return;
+    }
+
+    debug.visualizer.show_window(egui_context.ctx_mut());
+    egui::Window::new("Sent messages")
+        .default_pos([10.0, 400.0])
+        .show(egui_context.ctx_mut(), |ui| debug.stats.show(ui));
+}
+
+/// F1 toggles the overlay.
+pub fn client_network_debug(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut egui_context: ResMut<EguiContext>,
+    mut debug: ResMut<ClientNetworkDebug>,
+    client: Res<RenetClient>,
+) {
+    if keyboard_input.just_pressed(NETWORK_DEBUG_KEY) {
+        debug.visible = !debug.visible;
+    }
+    debug.visualizer.add_network_info(client.network_info());
+    debug.stats.tick(time.delta_seconds());
+    if !debug.visible {
+        return;
+    }
+
+    debug.visualizer.show_window(egui_context.ctx_mut());
+    egui::Window::new("Received messages")
+        .default_pos([10.0, 400.0])
+        .show(egui_context.ctx_mut(), |ui| debug.stats.show(ui));
+}
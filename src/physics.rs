@@ -1,12 +1,33 @@
-use bevy::prelude::*;
+use bevy::{prelude::*, utils::HashSet};
 use bevy_rapier3d::prelude::*;
-use bevy_renet::renet::RenetServer;
 
 use crate::{
+    arena::{track_arena_entities, Arena},
+    bot::Bot,
     cells::{Cell, NpcCell},
+    kill_feed::PlayerTag,
+    server::ServerSender,
+    team::Team,
     Player, PlayerInput, ServerChannel, ServerLobby, ServerMessages,
 };
 
+/// Sent on the server whenever a player cell is eaten.
+#[derive(Debug, Clone)]
+pub struct PlayerEaten {
+    // `None` when a bigger non-player cell did it
+    pub eater: Option<u64>,
+    pub victim: u64,
+    pub mass: f32,
+    pub arena: Arena,
+}
+
+/// Sent on the server whenever a player cell eats food.
+#[derive(Debug, Clone)]
+pub struct FoodEaten {
+    pub eater: u64,
+    pub arena: Arena,
+}
+
 #[derive(Bundle)]
 pub struct PhysicsBundle {
     flags: ActiveEvents,
@@ -35,20 +56,21 @@ impl PhysicsBundle {
 fn cell_collision_detection(
     mut commands: Commands,
     mut player_query: Query<
-        (Entity, &mut Player, &mut Cell),
+        (Entity, &mut Player, &mut Cell, &Arena),
         (With<Player>, With<Cell>, Without<NpcCell>),
     >,
     mut colliding_entities_query: Query<
         (Entity, &CollidingEntities, &mut Cell),
         (With<Cell>, With<NpcCell>),
     >,
-    mut server: ResMut<RenetServer>,
+    mut food_eaten: EventWriter<FoodEaten>,
+    mut server: ServerSender,
 ) {
     for (cell_entity, colliding_entities, cell) in colliding_entities_query.iter_mut() {
-        for (player_entity, mut _player, mut player_cell) in player_query.iter_mut() {
+        for (player_entity, player, mut player_cell, arena) in player_query.iter_mut() {
             if colliding_entities.contains(player_entity) {
                 if player_cell.size > cell.size {
-                    let new_size = player_cell.size + cell.size / 2.0;
+                    let new_size = grown_size(player_cell.size, cell.size);
                     player_cell.size = new_size;
                     commands.entity(cell_entity).despawn_recursive();
                     let message = ServerMessages::UpdateEntityCell {
@@ -56,89 +78,139 @@ fn cell_collision_detection(
                         size: new_size,
                     };
                     let message = bincode::serialize(&message).unwrap();
-                    server.broadcast_message(ServerChannel::ServerMessages, message);
+                    server.broadcast_in_arena(*arena, ServerChannel::ServerMessages, message);
+                    food_eaten.send(FoodEaten {
+                        eater: player.id,
+                        arena: *arena,
+                    });
                 }
             }
         }
     }
 }
 
-fn cell_on_removal_system(mut server: ResMut<RenetServer>, removed_cells: RemovedComponents<Cell>) {
+fn cell_on_removal_system(mut server: ServerSender, removed_cells: RemovedComponents<Cell>) {
     for entity in removed_cells.iter() {
         let message = ServerMessages::DespawnEntity { entity };
         let message = bincode::serialize(&message).unwrap();
 
-        server.broadcast_message(ServerChannel::ServerMessages, message);
+        match server.arenas_mut().forget_entity(entity) {
+            Some(arena) => server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message),
+            None => server.broadcast_message(ServerChannel::ServerMessages, message),
+        }
     }
 }
 
+/// Size of a cell after eating one of `eaten_size`, for food and players alike.
+pub fn grown_size(eater_size: f32, eaten_size: f32) -> f32 {
+    eater_size + eaten_size / 2.0
+}
+
+/// Resolves a player cell touching a smaller or bigger cell.
+///
+/// The eater's `Cell` is updated here and clients are sent that value, so the
+/// server's size is the one everyone sees. Food is left to `cell_collision_detection`.
+#[allow(clippy::too_many_arguments)]
 fn player_to_player_collision_detection(
     mut commands: Commands,
     mut collision_events: EventReader<CollisionEvent>,
     mut lobby: ResMut<ServerLobby>,
-    cell_query: Query<Option<&Cell>>,
-    mut player_query: Query<(Entity, &mut Player), With<PlayerInput>>,
-    mut server: ResMut<RenetServer>,
+    mut cell_query: Query<&mut Cell>,
+    player_query: Query<(&Player, Option<&Bot>), With<PlayerInput>>,
+    team_query: Query<&Team>,
+    arena_query: Query<&Arena>,
+    mut server: ServerSender,
+    mut player_eaten: EventWriter<PlayerEaten>,
 ) {
+    // despawns only apply at the end of the stage, an eaten cell must not eat or be eaten again
+    let mut eaten_cells: HashSet<Entity> = HashSet::default();
     for collision_event in collision_events.iter() {
-        if let CollisionEvent::Started(entity1, entity2, _) = collision_event {
-            if let Ok(Some(cell_1)) = cell_query.get(*entity1) {
-                if let Ok(Some(cell_2)) = cell_query.get(*entity2) {
-                    if cell_1.size > cell_2.size {
-                        for (player_entity, player) in player_query.iter_mut() {
-                            println!("player: {:?}", player_entity);
-                            if player_entity == *entity1 {
-                                println!("is growing: {:?}", player_entity);
-                                let new_size = cell_1.size + cell_2.size / 2.0;
-                                let message = ServerMessages::UpdateEntityCell {
-                                    entity: player_entity,
-                                    size: new_size,
-                                };
-                                let message = bincode::serialize(&message).unwrap();
-                                server.broadcast_message(ServerChannel::ServerMessages, message);
-                            } else if player_entity == *entity2 {
-                                println!("is dying: {:?}", player_entity);
-
-                                if let Some(player_entity) = lobby.players.remove(&player.id) {
-                                    commands.entity(player_entity).despawn();
-                                }
-
-                                let message = bincode::serialize(&ServerMessages::PlayerRemove {
-                                    id: player.id,
-                                })
-                                .unwrap();
-                                server.broadcast_message(ServerChannel::ServerMessages, message);
-                            }
-                        }
-                    } else if cell_1.size < cell_2.size {
-                        for (player_entity, player) in player_query.iter_mut() {
-                            if player_entity == *entity2 {
-                                println!("is growing: {:?}", player_entity);
-
-                                let new_size = cell_2.size + cell_1.size / 2.0;
-                                let message = ServerMessages::UpdateEntityCell {
-                                    entity: player_entity,
-                                    size: new_size,
-                                };
-                                let message = bincode::serialize(&message).unwrap();
-                                server.broadcast_message(ServerChannel::ServerMessages, message);
-                            } else if player_entity == *entity1 {
-                                if let Some(player_entity) = lobby.players.remove(&player.id) {
-                                    commands.entity(player_entity).despawn();
-                                }
-                                println!("is dying: {:?}", player_entity);
-
-                                let message = bincode::serialize(&ServerMessages::PlayerRemove {
-                                    id: player.id,
-                                })
-                                .unwrap();
-                                server.broadcast_message(ServerChannel::ServerMessages, message);
-                            }
-                        }
-                    }
-                }
+        let (entity1, entity2) = match collision_event {
+            CollisionEvent::Started(entity1, entity2, _) => (*entity1, *entity2),
+            CollisionEvent::Stopped(..) => continue,
+        };
+        if eaten_cells.contains(&entity1) || eaten_cells.contains(&entity2) {
+            continue;
+        }
+        let (size_1, size_2) = match (cell_query.get(entity1), cell_query.get(entity2)) {
+            (Ok(cell_1), Ok(cell_2)) => (cell_1.size, cell_2.size),
+            _ => continue,
+        };
+        let (eater, eaten, eaten_size) = if size_1 > size_2 {
+            (entity1, entity2, size_2)
+        } else if size_1 < size_2 {
+            (entity2, entity1, size_1)
+        } else {
+            continue;
+        };
+        let (victim, victim_bot) = match player_query.get(eaten) {
+            Ok(victim) => victim,
+            Err(_) => continue,
+        };
+
+        // no friendly eating, only player cells carry a team
+        if let (Ok(eater_team), Ok(eaten_team)) = (team_query.get(eater), team_query.get(eaten)) {
+            if eater_team == eaten_team {
+                continue;
             }
         }
+        eaten_cells.insert(eaten);
+
+        // collision groups keep both cells in the same arena
+        let arena = arena_query.get(eaten).copied().unwrap_or_default();
+        let eater_player = player_query.get(eater).ok().map(|(player, _)| player);
+        if eater_player.is_some() {
+            if let Ok(mut eater_cell) = cell_query.get_mut(eater) {
+                eater_cell.size = grown_size(eater_cell.size, eaten_size);
+                let message = ServerMessages::UpdateEntityCell {
+                    entity: eater,
+                    size: eater_cell.size,
+                };
+                let message = bincode::serialize(&message).unwrap();
+                server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+            }
+        }
+
+        debug!(victim = victim.id, mass = eaten_size, "player eaten");
+        player_eaten.send(PlayerEaten {
+            eater: eater_player.map(|eater_player| eater_player.id),
+            victim: victim.id,
+            mass: eaten_size,
+            arena,
+        });
+        // bots have no connection to tell
+        if victim_bot.is_none() {
+            let killer = eater_player
+                .map(|eater_player| eater_player.name.clone())
+                .unwrap_or_else(|| "a cell".to_string());
+            let message = bincode::serialize(&ServerMessages::PlayerKilled {
+                killer,
+                mass: eaten_size,
+            })
+            .unwrap();
+            server.send_message(victim.id, ServerChannel::ServerMessages, message);
+        }
+
+        let message = bincode::serialize(&ServerMessages::PlayerEaten {
+            eater: eater_player.map(|eater_player| PlayerTag {
+                id: eater_player.id,
+                name: eater_player.name.clone(),
+            }),
+            victim: PlayerTag {
+                id: victim.id,
+                name: victim.name.clone(),
+            },
+            mass: eaten_size,
+        })
+        .unwrap();
+        server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+
+        if let Some(player_entity) = lobby.players.remove(&victim.id) {
+            commands.entity(player_entity).despawn();
+        }
+
+        let message = bincode::serialize(&ServerMessages::PlayerRemove { id: victim.id }).unwrap();
+        server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
     }
 }
 
@@ -146,8 +218,14 @@ pub struct PhysicsPlugin;
 
 impl Plugin for PhysicsPlugin {
     fn build(&self, app: &mut App) {
+        app.add_event::<PlayerEaten>();
+        app.add_event::<FoodEaten>();
         app.add_system(cell_collision_detection);
         app.add_system(player_to_player_collision_detection);
+        app.add_system_to_stage(
+            CoreStage::PostUpdate,
+            track_arena_entities.before(cell_on_removal_system),
+        );
         app.add_system_to_stage(CoreStage::PostUpdate, cell_on_removal_system);
         // app.add_system_to_stage(CoreStage::PostUpdate, player_on_removal_system);
     }
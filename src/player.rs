@@ -1,6 +1,11 @@
 use bevy::prelude::*;
+use bevy_rapier3d::prelude::{ActiveEvents, Collider, Velocity};
 
-use crate::{cells::Cell, GameState, Player, PlayerInput, FIELD_SIZE};
+use crate::{
+    arena::Arena, cells::Cell, physics::PhysicsBundle, rng::ServerRng, server::ServerSender,
+    spawn::SpawnPlacement, team::team_color, ConnectionData, GameState, Player, PlayerInput,
+    ServerChannel, ServerLobby, ServerMessages, FIELD_SIZE,
+};
 
 // #[derive(Reflect, Component, Default)]
 // #[reflect(Component)]
@@ -67,3 +72,66 @@ pub fn update_player_cell_size(
         transform.scale = Vec3::new(cell.size / 4.0, cell.size / 4.0, cell.size / 4.0);
     }
 }
+
+#[allow(clippy::too_many_arguments)]
+pub fn spawn_player(
+    commands: &mut Commands,
+    meshes: &mut Assets<Mesh>,
+    materials: &mut Assets<StandardMaterial>,
+    lobby: &mut ServerLobby,
+    server: &mut ServerSender,
+    rng: &mut ServerRng,
+    spawn_placement: &SpawnPlacement,
+    occupied: &[(Vec3, f32)],
+    id: u64,
+    arena: Arena,
+) -> Entity {
+    let rand_transform =
+        Transform::from_translation(spawn_placement.player_spawn_point(rng, occupied));
+    let name = lobby
+        .names
+        .get(&id)
+        .cloned()
+        .unwrap_or_else(|| ConnectionData::default().player_name(id));
+    let team = lobby.teams.get(&id).copied();
+    let mut player_entity = commands.spawn(PbrBundle {
+        mesh: meshes.add(Mesh::from(shape::Icosphere {
+            radius: INITIAL_PLAYER_SIZE,
+            subdivisions: 4,
+        })),
+        material: materials.add(team_color(team).into()),
+        transform: rand_transform,
+        ..Default::default()
+    });
+    player_entity
+        .insert(Player { id, name })
+        .insert(Cell {
+            size: INITIAL_PLAYER_SIZE,
+        })
+        .insert(Name::new("Player"))
+        .insert(PlayerInput::default())
+        .insert(Velocity::default())
+        .insert(ActiveEvents::COLLISION_EVENTS)
+        .insert(PhysicsBundle::moving_entity())
+        .insert(Collider::ball(INITIAL_PLAYER_SIZE / 2.0))
+        .insert(arena.collision_groups())
+        .insert(arena);
+    if let Some(team) = team {
+        player_entity.insert(team);
+    }
+    let player_entity = player_entity.id();
+
+    lobby.players.insert(id, player_entity);
+
+    let translation: [f32; 3] = rand_transform.translation.into();
+    let message = bincode::serialize(&ServerMessages::PlayerCreate {
+        id,
+        entity: player_entity,
+        translation,
+        team,
+    })
+    .unwrap();
+    server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+
+    player_entity
+}
@@ -0,0 +1,459 @@
+use std::{
+    fmt::Write as _,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+    thread::{self, JoinHandle},
+};
+
+use bevy::{app::AppExit, prelude::*, utils::HashMap};
+use bevy_renet::renet::ServerEvent;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cells::Cell,
+    physics::PlayerEaten,
+    server::ServerSender,
+    session::{SessionExpired, Sessions},
+    settings::ServerSettings,
+    team::{team_color_name, Team},
+    ConnectionData, Player, ServerChannel, ServerMessages,
+};
+
+// where the client keeps its profile key unless `--profile` says otherwise
+pub const DEFAULT_PROFILE_KEY_FILE: &str = "cagario_profile.key";
+// seconds between two writes of the profile file, when a game ended in between
+pub const PROFILE_SAVE_INTERVAL: f32 = 10.0;
+
+/// Reads the client's profile key, creating the file with a fresh key on first run.
+///
+/// The key is what the server files stats under, anyone holding it plays as that profile.
+pub fn load_profile_key(path: &Path) -> u64 {
+    if let Ok(contents) = fs::read_to_string(path) {
+        match contents.trim().parse() {
+            Ok(key) => return key,
+            Err(_) => warn!("Replacing unreadable profile key in {}.", path.display()),
+        }
+    }
+    // 0 means no profile
+    let key = rand::random::<u64>().max(1);
+    if let Err(e) = fs::write(path, format!("{}\n", key)) {
+        warn!("Failed to save profile key to {}: {}", path.display(), e);
+    }
+    key
+}
+
+/// Lifetime stats as shown on the client's stats screen.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileStats {
+    pub games_played: u32,
+    pub best_mass: f32,
+    pub kills: u32,
+    // seconds, over every game
+    pub time_alive: f32,
+    pub favorite_color: Option<String>,
+}
+
+/// Everything the server remembers about one profile key.
+#[derive(Debug, Default, Clone)]
+pub struct PlayerProfile {
+    // the last name played under
+    pub name: String,
+    pub games_played: u32,
+    pub best_mass: f32,
+    pub kills: u32,
+    pub time_alive: f32,
+    // seconds alive per team colour, the longest one is the favourite
+    pub color_time: HashMap<String, f32>,
+}
+
+impl PlayerProfile {
+    pub fn favorite_color(&self) -> Option<&str> {
+        self.color_time
+            .iter()
+            .max_by(|a, b| a.1.total_cmp(b.1).then(b.0.cmp(a.0)))
+            .map(|(color, _)| color.as_str())
+    }
+
+    pub fn stats(&self) -> ProfileStats {
+        ProfileStats {
+            games_played: self.games_played,
+            best_mass: self.best_mass,
+            kills: self.kills,
+            time_alive: self.time_alive,
+            favorite_color: self.favorite_color().map(str::to_string),
+        }
+    }
+}
+
+// one cell's life, folded into the profile when it ends
+#[derive(Debug)]
+struct Life {
+    started: f64,
+    best_mass: f32,
+    kills: u32,
+    // `None` when the cell's colour says nothing about the player
+    color: Option<&'static str>,
+}
+
+/// Player profiles kept in a flat file, only inserted when `--profiles` is passed.
+///
+/// A game is one life of a cell: it ends when the cell is eaten or its client
+/// disconnects. The file is rewritten by `save_profiles` every `PROFILE_SAVE_INTERVAL`
+/// when a game ended, on a background thread like replays, and once more on exit.
+#[derive(Debug, Default, Resource)]
+pub struct ProfileStore {
+    profiles: HashMap<u64, PlayerProfile>,
+    // profile key of every connected client that sent one
+    keys: HashMap<u64, u64>,
+    lives: HashMap<u64, Life>,
+    path: Option<PathBuf>,
+    // a game ended since the file was last written
+    dirty: bool,
+    save_timer: Timer,
+    // both are taken by `close`, only set when there is a file
+    sender: Option<Sender<String>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl ProfileStore {
+    /// A missing file is an empty store, it gets created when the first game ends.
+    pub fn load(path: &Path) -> io::Result<Self> {
+        // `Drop` rules out struct update syntax
+        let mut store = Self::default();
+        store.path = Some(path.to_path_buf());
+        if path.exists() {
+            store.read()?;
+        }
+
+        let (sender, receiver) = channel::<String>();
+        let writer_path = path.to_path_buf();
+        let writer = thread::spawn(move || {
+            for contents in receiver {
+                if let Err(e) = fs::write(&writer_path, contents) {
+                    error!(
+                        "Failed to write player profiles {}: {}",
+                        writer_path.display(),
+                        e
+                    );
+                }
+            }
+        });
+        store.sender = Some(sender);
+        store.writer = Some(writer);
+        store.save_timer = Timer::from_seconds(PROFILE_SAVE_INTERVAL, TimerMode::Repeating);
+        Ok(store)
+    }
+
+    /// `None` when profiles are off, or when the file can't be read, so it is never overwritten.
+    pub fn from_settings(settings: &ServerSettings) -> Option<Self> {
+        let path = settings.profiles.as_ref()?;
+        match Self::load(path) {
+            Ok(store) => {
+                info!("Loaded {} player profiles.", store.len());
+                Some(store)
+            }
+            Err(e) => {
+                error!("Failed to read player profiles {}: {}", path.display(), e);
+                None
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.profiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.profiles.is_empty()
+    }
+
+    pub fn profile(&self, key: u64) -> Option<&PlayerProfile> {
+        self.profiles.get(&key)
+    }
+
+    /// Stats of the profile `client_id` plays as, `None` for anonymous clients.
+    pub fn stats(&self, client_id: u64) -> Option<ProfileStats> {
+        let key = self.keys.get(&client_id)?;
+        Some(self.profiles.get(key)?.stats())
+    }
+
+    pub fn connect(&mut self, client_id: u64, key: u64, name: &str) {
+        if key == 0 {
+            return;
+        }
+        self.keys.insert(client_id, key);
+        self.profiles.entry(key).or_default().name = name.to_string();
+    }
+
+    pub fn begin_life(&mut self, client_id: u64, now: f64, color: Option<&'static str>) {
+        if !self.keys.contains_key(&client_id) {
+            return;
+        }
+        self.end_life(client_id, now, None);
+        self.lives.insert(
+            client_id,
+            Life {
+                started: now,
+                best_mass: 0.0,
+                kills: 0,
+                color,
+            },
+        );
+    }
+
+    pub fn record_mass(&mut self, client_id: u64, mass: f32) {
+        if let Some(life) = self.lives.get_mut(&client_id) {
+            life.best_mass = life.best_mass.max(mass);
+        }
+    }
+
+    pub fn record_kill(&mut self, client_id: u64) {
+        if let Some(life) = self.lives.get_mut(&client_id) {
+            life.kills += 1;
+        }
+    }
+
+    /// Folds the current life into the profile, returns false when there was none.
+    pub fn end_life(&mut self, client_id: u64, now: f64, final_mass: Option<f32>) -> bool {
+        let life = match self.lives.remove(&client_id) {
+            Some(life) => life,
+            None => return false,
+        };
+        let profile = match self
+            .keys
+            .get(&client_id)
+            .and_then(|key| self.profiles.get_mut(key))
+        {
+            Some(profile) => profile,
+            None => return false,
+        };
+        let alive = (now - life.started).max(0.0) as f32;
+        profile.games_played += 1;
+        profile.best_mass = profile
+            .best_mass
+            .max(life.best_mass)
+            .max(final_mass.unwrap_or_default());
+        profile.kills += life.kills;
+        profile.time_alive += alive;
+        if let Some(color) = life.color {
+            *profile.color_time.entry(color.to_string()).or_default() += alive;
+        }
+        self.dirty = true;
+        true
+    }
+
+    /// Ends the client's game if it had one, returns whether the profile changed.
+    pub fn disconnect(&mut self, client_id: u64, now: f64) -> bool {
+        let changed = self.end_life(client_id, now, None);
+        self.keys.remove(&client_id);
+        changed
+    }
+
+    // key, games, best mass, kills, seconds alive, colour=seconds list and name, tab separated
+    fn read(&mut self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let contents = fs::read_to_string(path)?;
+        self.profiles.clear();
+        for line in contents.lines() {
+            if line.starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            match parse_profile(line) {
+                Some((key, profile)) => {
+                    self.profiles.insert(key, profile);
+                }
+                None => warn!("Skipping player profile line: {}", line),
+            }
+        }
+        Ok(())
+    }
+
+    /// Hands the file to the writer thread if a game ended since the last save.
+    pub fn save(&mut self) {
+        if !self.dirty {
+            return;
+        }
+        self.dirty = false;
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(self.contents());
+        }
+    }
+
+    /// Saves and waits for the writer thread to finish, nothing is written afterwards.
+    pub fn close(&mut self) {
+        self.save();
+        // the writer thread stops once the channel is closed
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                error!("Player profile writer thread panicked.");
+            }
+        }
+    }
+
+    fn contents(&self) -> String {
+        let mut keys: Vec<&u64> = self.profiles.keys().collect();
+        keys.sort_unstable();
+
+        let mut contents = String::from(
+            "# key\tgames\tbest mass\tkills\tseconds alive\tseconds per colour\tname\n",
+        );
+        for key in keys {
+            let profile = &self.profiles[key];
+            let mut colors: Vec<(&String, &f32)> = profile.color_time.iter().collect();
+            colors.sort_unstable_by(|a, b| a.0.cmp(b.0));
+            let colors: Vec<String> = colors
+                .into_iter()
+                .map(|(color, seconds)| format!("{}={:.1}", color, seconds))
+                .collect();
+            // names are the only free text, keep them on their own line and column
+            let name: String = profile
+                .name
+                .chars()
+                .map(|c| if c.is_control() { ' ' } else { c })
+                .collect();
+            let _ = writeln!(
+                contents,
+                "{}\t{}\t{:.2}\t{}\t{:.1}\t{}\t{}",
+                key,
+                profile.games_played,
+                profile.best_mass,
+                profile.kills,
+                profile.time_alive,
+                colors.join(","),
+                name
+            );
+        }
+        contents
+    }
+}
+
+impl Drop for ProfileStore {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn parse_profile(line: &str) -> Option<(u64, PlayerProfile)> {
+    let mut fields = line.splitn(7, '\t');
+    let key = fields.next()?.trim().parse().ok()?;
+    let mut profile = PlayerProfile {
+        games_played: fields.next()?.trim().parse().ok()?,
+        best_mass: fields.next()?.trim().parse().ok()?,
+        kills: fields.next()?.trim().parse().ok()?,
+        time_alive: fields.next()?.trim().parse().ok()?,
+        ..Default::default()
+    };
+    for entry in fields.next()?.split(',').filter(|entry| !entry.is_empty()) {
+        let (color, seconds) = entry.split_once('=')?;
+        profile
+            .color_time
+            .insert(color.to_string(), seconds.parse().ok()?);
+    }
+    profile.name = fields.next().unwrap_or_default().to_string();
+    Some((key, profile))
+}
+
+fn send_stats(store: &ProfileStore, server: &mut ServerSender, client_id: u64) {
+    if let Some(stats) = store.stats(client_id) {
+        let message = bincode::serialize(&ServerMessages::Profile { stats }).unwrap();
+        server.send_message(client_id, ServerChannel::ServerMessages, message);
+    }
+}
+
+/// Runs after `server_update_system`, so a dropped client's game goes on while its cell waits.
+pub fn track_profile_connections(
+    time: Res<Time>,
+    mut server_events: EventReader<ServerEvent>,
+    mut expired: EventReader<SessionExpired>,
+    store: Option<ResMut<ProfileStore>>,
+    sessions: Res<Sessions>,
+    mut server: ServerSender,
+) {
+    let mut store = match store {
+        Some(store) => store,
+        None => return,
+    };
+    let now = time.elapsed_seconds_f64();
+    for event in server_events.iter() {
+        match event {
+            // the client's own drop, or a refused attempt to take over its cell
+            ServerEvent::ClientConnected(id, _) | ServerEvent::ClientDisconnected(id)
+                if sessions.is_suspended(*id) => {}
+            ServerEvent::ClientConnected(id, user_data) => {
+                let connection_data = ConnectionData::from_user_data(user_data);
+                store.connect(
+                    *id,
+                    connection_data.profile,
+                    &connection_data.player_name(*id),
+                );
+                send_stats(&store, &mut server, *id);
+            }
+            ServerEvent::ClientDisconnected(id) => {
+                store.disconnect(*id, now);
+            }
+        }
+    }
+    for SessionExpired { client_id } in expired.iter() {
+        store.disconnect(*client_id, now);
+    }
+}
+
+/// Starts a game when a cell spawns and ends it when the cell is eaten.
+pub fn track_profile_games(
+    time: Res<Time>,
+    store: Option<ResMut<ProfileStore>>,
+    spawned: Query<(&Player, Option<&Team>), Added<Player>>,
+    players: Query<(&Player, &Cell)>,
+    mut player_eaten: EventReader<PlayerEaten>,
+    mut server: ServerSender,
+) {
+    let mut store = match store {
+        Some(store) => store,
+        None => return,
+    };
+    let now = time.elapsed_seconds_f64();
+    for (player, team) in spawned.iter() {
+        store.begin_life(player.id, now, team_color_name(team.copied()));
+    }
+    for (player, cell) in players.iter() {
+        store.record_mass(player.id, cell.size);
+    }
+
+    for event in player_eaten.iter() {
+        if let Some(eater) = event.eater {
+            store.record_kill(eater);
+        }
+        if store.end_life(event.victim, now, Some(event.mass)) {
+            send_stats(&store, &mut server, event.victim);
+        }
+    }
+}
+
+/// Writes the file out now and then instead of on every eat, bots die a lot.
+pub fn save_profiles(time: Res<Time>, store: Option<ResMut<ProfileStore>>) {
+    if let Some(mut store) = store {
+        if store.save_timer.tick(time.delta()).just_finished() {
+            store.save();
+        }
+    }
+}
+
+/// The window closing exits the process without dropping resources, so the last games are saved here.
+pub fn close_profiles_on_exit(exit: EventReader<AppExit>, store: Option<ResMut<ProfileStore>>) {
+    if let Some(mut store) = store {
+        if !exit.is_empty() {
+            store.close();
+        }
+    }
+}
+
+/// The local player's lifetime stats, `None` until the server sends them.
+#[derive(Debug, Default, Resource)]
+pub struct ClientProfile {
+    pub stats: Option<ProfileStats>,
+}
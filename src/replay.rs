@@ -0,0 +1,639 @@
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Sender},
+    thread::{self, JoinHandle},
+    time::SystemTime,
+};
+
+use bevy::{app::AppExit, prelude::*};
+use bevy_egui::{egui, EguiContext};
+use bevy_renet::renet::RenetServer;
+use serde::{Deserialize, Serialize};
+use smooth_bevy_cameras::LookTransform;
+
+use crate::{
+    arena::Arena,
+    cells::{Cell, NpcCell},
+    client::{client_sync_players, ClientLobby, NetworkMapping},
+    leaderboard::ClientLeaderboard,
+    team::Team,
+    Player, ServerChannel, ServerMessages,
+};
+
+pub const REPLAY_MAGIC: &[u8; 4] = b"CGRP";
+// bump whenever ReplayHeader, ReplayFrame or the message enums change shape
+pub const REPLAY_VERSION: u16 = 8;
+pub const REPLAY_EXTENSION: &str = "cgr";
+
+/// Written once after the magic bytes at the start of every replay file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayHeader {
+    pub version: u16,
+    pub seed: u64,
+    // unix time in seconds
+    pub started_at: u64,
+    // room codes, indexed by `ReplayTarget::Arena`
+    pub rooms: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ReplayTarget {
+    Broadcast,
+    Client(u64),
+    // everyone in one arena
+    Arena(u32),
+}
+
+/// One outgoing payload, exactly as it was handed to renet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplayFrame {
+    pub tick: u64,
+    // seconds since the match started
+    pub time: f64,
+    pub channel: u8,
+    pub target: ReplayTarget,
+    pub payload: Vec<u8>,
+}
+
+enum RecorderCommand {
+    Start { path: PathBuf, header: ReplayHeader },
+    Frame(ReplayFrame),
+    Finish,
+}
+
+/// Records everything the server sends, one file per match.
+///
+/// Files are written on a background thread so a slow disk never stalls the
+/// game loop. A match starts when the first client connects and ends when the
+/// last one leaves.
+#[derive(Resource)]
+pub struct ReplayRecorder {
+    // both are taken by `close`
+    sender: Option<Sender<RecorderCommand>>,
+    writer: Option<JoinHandle<()>>,
+    dir: PathBuf,
+    seed: u64,
+    rooms: Vec<String>,
+    recording: bool,
+    matches: u32,
+    tick: u64,
+    match_started: f64,
+    now: f64,
+}
+
+impl ReplayRecorder {
+    pub fn new(dir: PathBuf, seed: u64, rooms: Vec<String>) -> Self {
+        let (sender, receiver) = channel();
+        let writer = thread::spawn(move || {
+            let mut writer: Option<BufWriter<File>> = None;
+            for command in receiver {
+                if let Err(e) = handle_command(&mut writer, command) {
+                    error!("Failed to write replay: {}", e);
+                    writer = None;
+                }
+            }
+            if let Some(mut writer) = writer {
+                let _ = writer.flush();
+            }
+        });
+
+        Self {
+            sender: Some(sender),
+            writer: Some(writer),
+            dir,
+            seed,
+            rooms,
+            recording: false,
+            matches: 0,
+            tick: 0,
+            match_started: 0.0,
+            now: 0.0,
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording
+    }
+
+    /// Closes the current file, if any, and starts writing a new one.
+    pub fn start_match(&mut self) -> PathBuf {
+        let started_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        self.matches += 1;
+        let path = self.dir.join(format!(
+            "match-{}-{}.{}",
+            started_at, self.matches, REPLAY_EXTENSION
+        ));
+
+        let header = ReplayHeader {
+            version: REPLAY_VERSION,
+            seed: self.seed,
+            started_at,
+            rooms: self.rooms.clone(),
+        };
+        self.send(RecorderCommand::Start {
+            path: path.clone(),
+            header,
+        });
+        self.recording = true;
+        self.match_started = self.now;
+        path
+    }
+
+    pub fn finish_match(&mut self) {
+        self.send(RecorderCommand::Finish);
+        self.recording = false;
+    }
+
+    /// Flushes the current file and waits for the writer thread to exit.
+    ///
+    /// Nothing is recorded afterwards.
+    pub fn close(&mut self) {
+        self.finish_match();
+        // the writer thread stops once the channel is closed
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            if writer.join().is_err() {
+                error!("Replay writer thread panicked.");
+            }
+        }
+    }
+
+    fn send(&self, command: RecorderCommand) {
+        if let Some(sender) = &self.sender {
+            let _ = sender.send(command);
+        }
+    }
+
+    pub fn record(&mut self, channel: u8, target: ReplayTarget, payload: &[u8]) {
+        if !self.recording {
+            return;
+        }
+        let frame = ReplayFrame {
+            tick: self.tick,
+            time: self.now - self.match_started,
+            channel,
+            target,
+            payload: payload.to_vec(),
+        };
+        self.send(RecorderCommand::Frame(frame));
+    }
+}
+
+impl Drop for ReplayRecorder {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+fn handle_command(
+    writer: &mut Option<BufWriter<File>>,
+    command: RecorderCommand,
+) -> bincode::Result<()> {
+    match command {
+        RecorderCommand::Start { path, header } => {
+            if let Some(mut previous) = writer.take() {
+                previous.flush()?;
+            }
+            if let Some(dir) = path.parent() {
+                fs::create_dir_all(dir)?;
+            }
+            let mut file = BufWriter::new(File::create(&path)?);
+            file.write_all(REPLAY_MAGIC)?;
+            bincode::serialize_into(&mut file, &header)?;
+            *writer = Some(file);
+        }
+        RecorderCommand::Frame(frame) => {
+            if let Some(file) = writer.as_mut() {
+                bincode::serialize_into(file, &frame)?;
+            }
+        }
+        RecorderCommand::Finish => {
+            if let Some(mut file) = writer.take() {
+                file.flush()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub fn advance_replay_clock(time: Res<Time>, recorder: Option<ResMut<ReplayRecorder>>) {
+    if let Some(mut recorder) = recorder {
+        recorder.tick += 1;
+        recorder.now = time.elapsed_seconds_f64();
+    }
+}
+
+/// The window closing exits the process without dropping resources, so the file is closed here.
+pub fn close_replay_on_exit(exit: EventReader<AppExit>, recorder: Option<ResMut<ReplayRecorder>>) {
+    if let Some(mut recorder) = recorder {
+        if !exit.is_empty() {
+            recorder.close();
+        }
+    }
+}
+
+/// Starts a new file when the first client joins and closes it when the last one leaves.
+///
+/// Every file opens with a snapshot of the cells already in the arena, so it can
+/// be played back on its own.
+pub fn rotate_replay_per_match(
+    recorder: Option<ResMut<ReplayRecorder>>,
+    server: Res<RenetServer>,
+    players: Query<(Entity, &Player, &Cell, &Transform, Option<&Team>, &Arena)>,
+    npc_cells: Query<(Entity, &Cell, &Transform, &Arena), With<NpcCell>>,
+) {
+    let mut recorder = match recorder {
+        Some(recorder) => recorder,
+        None => return,
+    };
+
+    let has_clients = !server.clients_id().is_empty();
+    if has_clients && !recorder.is_recording() {
+        let path = recorder.start_match();
+        info!("Recording match to {}.", path.display());
+
+        let mut snapshot = Vec::new();
+        for (entity, player, cell, transform, team, arena) in players.iter() {
+            let message = ServerMessages::PlayerCreate {
+                entity,
+                id: player.id,
+                translation: transform.translation.into(),
+                team: team.copied(),
+            };
+            snapshot.push((*arena, message));
+            // players are created at the starting size, as for a joining client
+            let message = ServerMessages::UpdateEntityCell {
+                entity,
+                size: cell.size,
+            };
+            snapshot.push((*arena, message));
+        }
+        for (entity, cell, transform, arena) in npc_cells.iter() {
+            let message = ServerMessages::SpawnNpcCell {
+                entity,
+                translation: transform.translation.into(),
+                size: cell.size,
+            };
+            snapshot.push((*arena, message));
+        }
+        for (arena, message) in snapshot {
+            let message = bincode::serialize(&message).unwrap();
+            recorder.record(
+                ServerChannel::ServerMessages.into(),
+                ReplayTarget::Arena(arena.0),
+                &message,
+            );
+        }
+    } else if !has_clients && recorder.is_recording() {
+        recorder.finish_match();
+    }
+}
+
+/// Reads a whole replay file into memory.
+///
+/// A file cut short by a crash still loads, up to its last complete frame.
+pub fn read_replay(path: &Path) -> io::Result<(ReplayHeader, Vec<ReplayFrame>)> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if &magic != REPLAY_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a replay file",
+        ));
+    }
+    let header: ReplayHeader = bincode::deserialize_from(&mut reader)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if header.version != REPLAY_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "replay version {} is not supported, expected {}",
+                header.version, REPLAY_VERSION
+            ),
+        ));
+    }
+
+    let mut frames = Vec::new();
+    loop {
+        match bincode::deserialize_from::<_, ReplayFrame>(&mut reader) {
+            Ok(frame) => frames.push(frame),
+            Err(e) => {
+                if let bincode::ErrorKind::Io(io_error) = e.as_ref() {
+                    if io_error.kind() == io::ErrorKind::UnexpectedEof {
+                        break;
+                    }
+                }
+                return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+            }
+        }
+    }
+    Ok((header, frames))
+}
+
+pub const REPLAY_SPEEDS: [f32; 6] = [0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// Plays a recorded match back in place of a `RenetClient`.
+///
+/// Only broadcast frames and frames for the arena being watched are replayed;
+/// messages the server sent to a single client (the initial world, per-client
+/// leaderboards) would otherwise show up twice or from the wrong point of view.
+#[derive(Resource)]
+pub struct ReplayPlayback {
+    pub header: ReplayHeader,
+    frames: Vec<ReplayFrame>,
+    // every arena with frames, and the one being watched
+    arenas: Vec<u32>,
+    arena: u32,
+    cursor: usize,
+    time: f64,
+    pub speed: f32,
+    pub paused: bool,
+    // applied by the next `advance`, so a seek never lands between the rewind and the sync
+    seek_to: Option<f64>,
+    // set when seeking backwards or switching arenas, the world has to be rebuilt from the start
+    rewind: bool,
+    server_messages: VecDeque<Vec<u8>>,
+    networked_entities: VecDeque<Vec<u8>>,
+}
+
+impl ReplayPlayback {
+    pub fn new(header: ReplayHeader, frames: Vec<ReplayFrame>) -> Self {
+        let frames: Vec<ReplayFrame> = frames
+            .into_iter()
+            .filter(|frame| !matches!(frame.target, ReplayTarget::Client(_)))
+            .collect();
+        let mut arenas = vec![0];
+        for frame in frames.iter() {
+            if let ReplayTarget::Arena(arena) = frame.target {
+                if !arenas.contains(&arena) {
+                    arenas.push(arena);
+                }
+            }
+        }
+        arenas.sort_unstable();
+        Self {
+            header,
+            frames,
+            arenas,
+            arena: 0,
+            cursor: 0,
+            time: 0.0,
+            speed: 1.0,
+            paused: false,
+            seek_to: None,
+            rewind: false,
+            server_messages: VecDeque::new(),
+            networked_entities: VecDeque::new(),
+        }
+    }
+
+    pub fn time(&self) -> f64 {
+        self.seek_to.unwrap_or(self.time)
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.frames
+            .last()
+            .map(|frame| frame.time)
+            .unwrap_or_default()
+    }
+
+    /// Jumps to `time` on the next `advance`, paused or not.
+    pub fn seek(&mut self, time: f64) {
+        self.seek_to = Some(time.clamp(0.0, self.duration()));
+    }
+
+    pub fn arena(&self) -> u32 {
+        self.arena
+    }
+
+    /// The arena's room code, or its number for replays that didn't record one.
+    pub fn room(&self, arena: u32) -> String {
+        self.header
+            .rooms
+            .get(arena as usize)
+            .cloned()
+            .unwrap_or_else(|| format!("arena {}", arena))
+    }
+
+    /// Every arena the replay has frames for, the first one included.
+    pub fn arenas(&self) -> &[u32] {
+        &self.arenas
+    }
+
+    /// Watches another arena from the current time on, rebuilding its world on the next `advance`.
+    pub fn select_arena(&mut self, arena: u32) {
+        if arena == self.arena {
+            return;
+        }
+        self.arena = arena;
+        self.seek_to = Some(self.time());
+        self.restart();
+    }
+
+    pub fn advance(&mut self, delta: f64) {
+        if let Some(time) = self.seek_to.take() {
+            if time < self.time {
+                self.restart();
+            }
+            self.time = time;
+            self.queue_due_frames();
+            return;
+        }
+        if self.paused {
+            return;
+        }
+        self.time = (self.time + delta * self.speed as f64).min(self.duration());
+        self.queue_due_frames();
+    }
+
+    fn restart(&mut self) {
+        self.cursor = 0;
+        self.rewind = true;
+        self.server_messages.clear();
+        self.networked_entities.clear();
+    }
+
+    fn queue_due_frames(&mut self) {
+        while let Some(frame) = self.frames.get(self.cursor) {
+            if frame.time > self.time {
+                break;
+            }
+            self.cursor += 1;
+            let watched = match frame.target {
+                ReplayTarget::Arena(arena) => arena == self.arena,
+                _ => true,
+            };
+            if !watched {
+                continue;
+            }
+            if frame.channel == u8::from(ServerChannel::NetworkedEntities) {
+                // only the latest positions matter, which keeps seeking cheap
+                self.networked_entities.clear();
+                self.networked_entities.push_back(frame.payload.clone());
+            } else if frame.channel == u8::from(ServerChannel::ServerMessages) {
+                self.server_messages.push_back(frame.payload.clone());
+            }
+        }
+    }
+
+    pub fn receive_message<C: Into<u8>>(&mut self, channel: C) -> Option<Vec<u8>> {
+        let channel = channel.into();
+        if channel == u8::from(ServerChannel::ServerMessages) {
+            self.server_messages.pop_front()
+        } else if channel == u8::from(ServerChannel::NetworkedEntities) {
+            self.networked_entities.pop_front()
+        } else {
+            None
+        }
+    }
+}
+
+/// Feeds a `ReplayPlayback` resource to `client_sync_players`, no server needed.
+///
+/// Seeking backwards rebuilds the world from the first frame within a single update.
+pub struct ReplayPlaybackPlugin;
+
+impl Plugin for ReplayPlaybackPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(advance_replay_playback)
+            .add_system(rewind_replay_world.after(advance_replay_playback))
+            .add_system(client_sync_players.after(rewind_replay_world));
+    }
+}
+
+pub fn advance_replay_playback(time: Res<Time>, mut playback: ResMut<ReplayPlayback>) {
+    playback.advance(time.delta_seconds_f64());
+}
+
+/// Clears everything the replay spawned so a backwards seek can rebuild it from the first frame.
+pub fn rewind_replay_world(
+    mut commands: Commands,
+    mut playback: ResMut<ReplayPlayback>,
+    mut lobby: ResMut<ClientLobby>,
+    mut network_mapping: ResMut<NetworkMapping>,
+    mut leaderboard: ResMut<ClientLeaderboard>,
+) {
+    if !playback.rewind {
+        return;
+    }
+    playback.rewind = false;
+
+    for (_, client_entity) in network_mapping.0.drain() {
+        commands.entity(client_entity).despawn();
+    }
+    lobby.players.clear();
+    *leaderboard = ClientLeaderboard::default();
+}
+
+pub fn replay_controls(
+    mut egui_context: ResMut<EguiContext>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut playback: ResMut<ReplayPlayback>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        playback.paused = !playback.paused;
+    }
+
+    egui::Window::new("Replay")
+        .anchor(egui::Align2::CENTER_BOTTOM, [0.0, -10.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.horizontal(|ui| {
+                let label = if playback.paused { "Play" } else { "Pause" };
+                if ui.button(label).clicked() {
+                    playback.paused = !playback.paused;
+                }
+
+                let mut time = playback.time();
+                let duration = playback.duration();
+                let slider = egui::Slider::new(&mut time, 0.0..=duration)
+                    .show_value(false)
+                    .step_by(0.1);
+                if ui.add(slider).changed() {
+                    playback.seek(time);
+                }
+                ui.label(format!("{:.1}s / {:.1}s", playback.time(), duration));
+
+                for speed in REPLAY_SPEEDS {
+                    if ui
+                        .selectable_label(playback.speed == speed, format!("{}x", speed))
+                        .clicked()
+                    {
+                        playback.speed = speed;
+                    }
+                }
+            });
+            if playback.arenas().len() > 1 {
+                ui.horizontal(|ui| {
+                    ui.label("Room:");
+                    for arena in playback.arenas().to_vec() {
+                        let selected = playback.arena() == arena;
+                        if ui
+                            .selectable_label(selected, playback.room(arena))
+                            .clicked()
+                        {
+                            playback.select_arena(arena);
+                        }
+                    }
+                });
+            }
+            ui.label(format!(
+                "seed {}   space: pause   WASD: pan   Q/E: zoom",
+                playback.header.seed
+            ));
+        });
+}
+
+const FREE_CAMERA_SPEED: f32 = 40.0;
+
+/// Pans and zooms the camera, there is no controlled player to follow in a replay.
+pub fn replay_free_camera(
+    time: Res<Time>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut camera_query: Query<&mut LookTransform, With<Camera>>,
+) {
+    let mut direction = Vec3::ZERO;
+    if keyboard_input.pressed(KeyCode::W) || keyboard_input.pressed(KeyCode::Up) {
+        direction.z -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::S) || keyboard_input.pressed(KeyCode::Down) {
+        direction.z += 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::A) || keyboard_input.pressed(KeyCode::Left) {
+        direction.x -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::D) || keyboard_input.pressed(KeyCode::Right) {
+        direction.x += 1.0;
+    }
+    let mut zoom = 0.0;
+    if keyboard_input.pressed(KeyCode::Q) {
+        zoom -= 1.0;
+    }
+    if keyboard_input.pressed(KeyCode::E) {
+        zoom += 1.0;
+    }
+
+    let step = FREE_CAMERA_SPEED * time.delta_seconds();
+    for mut look in camera_query.iter_mut() {
+        let pan = direction * step;
+        look.eye += pan;
+        look.target += pan;
+
+        // move along the view direction, without passing through the target
+        let view = look.target - look.eye;
+        let zoom_step = view.normalize_or_zero() * zoom * step;
+        if zoom <= 0.0 || view.length() > zoom_step.length() + 1.0 {
+            look.eye += zoom_step;
+        }
+    }
+}
@@ -0,0 +1,52 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+use crate::settings::ServerSettings;
+
+/// The only source of randomness on the server.
+///
+/// Every random decision (food, spawn points) draws from this resource, so a
+/// match can be replayed by starting the server with the same seed.
+#[derive(Resource)]
+pub struct ServerRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl ServerRng {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// Uses the configured seed, or picks a fresh one when none was given.
+    pub fn from_settings(settings: &ServerSettings) -> Self {
+        let seed = settings.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        info!("Using random seed {}.", seed);
+        Self::new(seed)
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for ServerRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
@@ -0,0 +1,336 @@
+use bevy::{prelude::*, utils::HashMap};
+use bevy_egui::{egui, EguiContext};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    arena::{Arena, Arenas},
+    cells::{Cell, NpcCell},
+    physics::PlayerEaten,
+    player::{spawn_player, INITIAL_PLAYER_SIZE},
+    rng::ServerRng,
+    server::ServerSender,
+    spawn::SpawnPlacement,
+    GameState, Player, ServerChannel, ServerLobby, ServerMessages,
+};
+
+// how long the results stay up before the arena resets, in seconds
+pub const ROUND_RESULTS_DURATION: f32 = 10.0;
+pub const ROUND_RESULTS_SIZE: usize = 10;
+// how often the countdown is sent, clients tick it down in between
+pub const ROUND_COUNTDOWN_INTERVAL: f32 = 1.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoundResult {
+    pub id: u64,
+    pub name: String,
+    pub mass: f32,
+    pub kills: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RoundPhase {
+    Playing,
+    Results,
+}
+
+/// Round mode in one arena, for arenas with a round length.
+///
+/// A round plays for `duration` seconds, shows the results for
+/// `ROUND_RESULTS_DURATION`, then the arena is reset and the next round starts.
+#[derive(Debug)]
+pub struct Round {
+    pub number: u32,
+    pub duration: f32,
+    pub phase: RoundPhase,
+    phase_timer: Timer,
+    countdown_timer: Timer,
+    kills: HashMap<u64, u32>,
+    reset_pending: bool,
+}
+
+impl Round {
+    pub fn new(duration: f32) -> Self {
+        Self {
+            number: 1,
+            duration,
+            phase: RoundPhase::Playing,
+            phase_timer: Timer::from_seconds(duration, TimerMode::Once),
+            countdown_timer: Timer::from_seconds(ROUND_COUNTDOWN_INTERVAL, TimerMode::Repeating),
+            kills: HashMap::default(),
+            reset_pending: false,
+        }
+    }
+
+    pub fn remaining(&self) -> f32 {
+        self.phase_timer.duration().as_secs_f32() - self.phase_timer.elapsed_secs()
+    }
+}
+
+/// The rounds of every arena that plays them, each on its own clock.
+#[derive(Debug, Default, Resource)]
+pub struct Rounds {
+    rounds: HashMap<Arena, Round>,
+}
+
+impl Rounds {
+    pub fn from_arenas(arenas: &Arenas) -> Self {
+        let rounds = arenas
+            .iter()
+            .filter_map(|(arena, settings)| Some((arena, Round::new(settings.round_duration?))))
+            .collect();
+        Self { rounds }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rounds.is_empty()
+    }
+
+    pub fn get(&self, arena: Arena) -> Option<&Round> {
+        self.rounds.get(&arena)
+    }
+}
+
+pub fn count_round_kills(mut rounds: ResMut<Rounds>, mut player_eaten: EventReader<PlayerEaten>) {
+    for event in player_eaten.iter() {
+        if let (Some(eater), Some(round)) = (event.eater, rounds.rounds.get_mut(&event.arena)) {
+            *round.kills.entry(eater).or_default() += 1;
+        }
+    }
+}
+
+pub fn tick_round(
+    time: Res<Time>,
+    mut rounds: ResMut<Rounds>,
+    mut server: ServerSender,
+    players: Query<(&Player, &Cell, &Arena)>,
+) {
+    for (arena, round) in rounds.rounds.iter_mut() {
+        let arena = *arena;
+        round.phase_timer.tick(time.delta());
+        round.countdown_timer.tick(time.delta());
+
+        match round.phase {
+            RoundPhase::Playing if round.phase_timer.finished() => {
+                info!(
+                    "Round {} is over in {}.",
+                    round.number,
+                    server.arenas().settings(arena).code
+                );
+                let mut results: Vec<RoundResult> = players
+                    .iter()
+                    .filter(|(_, _, player_arena)| **player_arena == arena)
+                    .map(|(player, cell, _)| RoundResult {
+                        id: player.id,
+                        name: player.name.clone(),
+                        mass: cell.size,
+                        kills: round.kills.get(&player.id).copied().unwrap_or_default(),
+                    })
+                    .collect();
+                results.sort_by(|a, b| b.mass.total_cmp(&a.mass).then(a.id.cmp(&b.id)));
+                results.truncate(ROUND_RESULTS_SIZE);
+
+                let message = bincode::serialize(&ServerMessages::RoundResults {
+                    round: round.number,
+                    results,
+                    next_round_in: ROUND_RESULTS_DURATION,
+                })
+                .unwrap();
+                server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+
+                round.phase = RoundPhase::Results;
+                round.phase_timer = Timer::from_seconds(ROUND_RESULTS_DURATION, TimerMode::Once);
+            }
+            RoundPhase::Playing if round.countdown_timer.just_finished() => {
+                let message = bincode::serialize(&ServerMessages::RoundCountdown {
+                    round: round.number,
+                    remaining: round.remaining(),
+                })
+                .unwrap();
+                server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+            }
+            RoundPhase::Results if round.phase_timer.finished() => {
+                round.number += 1;
+                round.phase = RoundPhase::Playing;
+                round.phase_timer = Timer::from_seconds(round.duration, TimerMode::Once);
+                round.countdown_timer.reset();
+                round.kills.clear();
+                round.reset_pending = true;
+                info!(
+                    "Round {} starts in {}.",
+                    round.number,
+                    server.arenas().settings(arena).code
+                );
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Clears the food in every arena starting a new round and puts its players
+/// back at a fresh spawn point with the starting size.
+///
+/// Players still on the game over screen get a new cell too.
+#[allow(clippy::too_many_arguments)]
+pub fn reset_arenas(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut rounds: ResMut<Rounds>,
+    mut lobby: ResMut<ServerLobby>,
+    mut server: ServerSender,
+    mut rng: ResMut<ServerRng>,
+    spawn_placement: Res<SpawnPlacement>,
+    npc_cells: Query<(Entity, &Arena), With<NpcCell>>,
+    mut players: Query<
+        (Entity, &mut Cell, &mut Transform, &Arena),
+        (With<Player>, Without<NpcCell>),
+    >,
+) {
+    let mut resetting: Vec<Arena> = Vec::new();
+    for (arena, round) in rounds.rounds.iter_mut() {
+        if round.reset_pending {
+            round.reset_pending = false;
+            resetting.push(*arena);
+        }
+    }
+    if resetting.is_empty() {
+        return;
+    }
+
+    // every round gets its own replay, a new file starts once this one is closed
+    if let Some(recorder) = server.recorder_mut() {
+        if recorder.is_recording() {
+            recorder.finish_match();
+        }
+    }
+
+    for (entity, arena) in npc_cells.iter() {
+        if resetting.contains(arena) {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+
+    let mut occupied: HashMap<Arena, Vec<(Vec3, f32)>> = HashMap::default();
+    for (entity, mut cell, mut transform, arena) in players.iter_mut() {
+        if !resetting.contains(arena) {
+            continue;
+        }
+        let occupied = occupied.entry(*arena).or_default();
+        cell.size = INITIAL_PLAYER_SIZE;
+        transform.translation = spawn_placement.player_spawn_point(&mut *rng, occupied);
+        occupied.push((transform.translation, cell.size));
+
+        let message = bincode::serialize(&ServerMessages::UpdateEntityCell {
+            entity,
+            size: cell.size,
+        })
+        .unwrap();
+        server.broadcast_in_arena(*arena, ServerChannel::ServerMessages, message);
+    }
+
+    // refused clients never got a name nor an arena, they are only waiting to be disconnected
+    let eaten: Vec<u64> = server
+        .clients_id()
+        .into_iter()
+        .filter(|id| {
+            lobby.names.contains_key(id)
+                && !lobby.players.contains_key(id)
+                && !lobby.spectators.contains(id)
+        })
+        .filter(|id| {
+            server.arenas().is_assigned(*id) && resetting.contains(&server.arenas().arena_of(*id))
+        })
+        .collect();
+    for id in eaten {
+        let arena = server.arenas().arena_of(id);
+        spawn_player(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            &mut lobby,
+            &mut server,
+            &mut rng,
+            &spawn_placement,
+            occupied.get(&arena).map(Vec::as_slice).unwrap_or_default(),
+            id,
+            arena,
+        );
+    }
+}
+
+/// What the client knows about the current round.
+#[derive(Debug, Default, Resource)]
+pub struct ClientRound {
+    pub round: u32,
+    // ticked down locally between countdown messages
+    pub remaining: Option<f32>,
+    pub results: Vec<RoundResult>,
+    pub next_round_in: f32,
+}
+
+pub struct RoundPlugin;
+
+impl Plugin for RoundPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ClientRound>().add_system_set(
+            SystemSet::on_update(GameState::InGame)
+                .with_system(tick_client_round)
+                .with_system(round_window.after(tick_client_round)),
+        );
+    }
+}
+
+fn tick_client_round(time: Res<Time>, mut round: ResMut<ClientRound>) {
+    let delta = time.delta_seconds();
+    if let Some(remaining) = round.remaining.as_mut() {
+        *remaining = (*remaining - delta).max(0.0);
+    }
+    round.next_round_in = (round.next_round_in - delta).max(0.0);
+}
+
+fn round_window(mut egui_context: ResMut<EguiContext>, round: Res<ClientRound>) {
+    if let Some(remaining) = round.remaining {
+        let seconds = remaining.ceil() as u32;
+        egui::Window::new("Round")
+            .anchor(egui::Align2::CENTER_TOP, [0.0, 10.0])
+            .title_bar(false)
+            .resizable(false)
+            .show(egui_context.ctx_mut(), |ui| {
+                ui.heading(format!(
+                    "Round {}  {}:{:02}",
+                    round.round,
+                    seconds / 60,
+                    seconds % 60
+                ));
+            });
+    }
+
+    if round.results.is_empty() {
+        return;
+    }
+    egui::Window::new(format!("Round {} results", round.round))
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            egui::Grid::new("round_results_grid").show(ui, |ui| {
+                ui.strong("#");
+                ui.strong("Player");
+                ui.strong("Mass");
+                ui.strong("Kills");
+                ui.end_row();
+                for (index, result) in round.results.iter().enumerate() {
+                    ui.label(format!("{}", index + 1));
+                    ui.label(&result.name);
+                    ui.label(format!("{:.1}", result.mass));
+                    ui.label(format!("{}", result.kills));
+                    ui.end_row();
+                }
+            });
+            ui.separator();
+            ui.label(format!(
+                "Next round in {}s",
+                round.next_round_in.ceil() as u32
+            ));
+        });
+}
@@ -0,0 +1,419 @@
+use std::{
+    marker::PhantomData,
+    ops::{Deref, DerefMut},
+};
+
+use bevy::{ecs::system::SystemParam, prelude::*};
+use bevy_renet::renet::{RenetServer, ServerEvent};
+
+use crate::{
+    access::{check_connection, other_addresses, BanList, Refusals},
+    arena::{Arena, Arenas},
+    cells::{Cell, NpcCell},
+    metrics::ServerMetrics,
+    net_debug::ServerNetworkDebug,
+    player::spawn_player,
+    replay::{ReplayRecorder, ReplayTarget},
+    rng::ServerRng,
+    session::Sessions,
+    settings::ServerSettings,
+    spawn::SpawnPlacement,
+    team::{assign_team, Team},
+    validation::{decode, validate_command, AntiCheat, MessageKind},
+    ClientChannel, ConnectionData, NetworkedEntities, Player, PlayerCommand, PlayerInput,
+    ServerChannel, ServerLobby, ServerMessages,
+};
+
+pub const PLAYER_MOVE_SPEED: f32 = 20.0;
+
+/// `RenetServer` plus everything that needs to see outgoing messages.
+///
+/// Systems that send take this instead of `ResMut<RenetServer>`. It derefs to the
+/// server, so receiving and client queries work as before. It also owns the arena
+/// membership, since that decides who a message goes to.
+#[derive(SystemParam)]
+pub struct ServerSender<'w, 's> {
+    server: ResMut<'w, RenetServer>,
+    arenas: ResMut<'w, Arenas>,
+    recorder: Option<ResMut<'w, ReplayRecorder>>,
+    metrics: Option<ResMut<'w, ServerMetrics>>,
+    net_debug: Option<ResMut<'w, ServerNetworkDebug>>,
+    #[system_param(ignore)]
+    marker: PhantomData<&'s ()>,
+}
+
+impl<'w, 's> ServerSender<'w, 's> {
+    pub fn broadcast_message<C: Into<u8>>(&mut self, channel: C, message: Vec<u8>) {
+        let channel = channel.into();
+        let recipients = self.server.clients_id().len();
+        self.observe(channel, ReplayTarget::Broadcast, &message, recipients);
+        self.server.broadcast_message(channel, message);
+    }
+
+    pub fn send_message<C: Into<u8>>(&mut self, client_id: u64, channel: C, message: Vec<u8>) {
+        let channel = channel.into();
+        self.observe(channel, ReplayTarget::Client(client_id), &message, 1);
+        self.server.send_message(client_id, channel, message);
+    }
+
+    pub fn recorder_mut(&mut self) -> Option<&mut ReplayRecorder> {
+        self.recorder.as_deref_mut()
+    }
+
+    /// Sends to every client in `arena`, players and spectators alike.
+    pub fn broadcast_in_arena<C: Into<u8>>(&mut self, arena: Arena, channel: C, message: Vec<u8>) {
+        let channel = channel.into();
+        let recipients: Vec<u64> = self
+            .server
+            .clients_id()
+            .into_iter()
+            .filter(|client_id| self.arenas.is_member(*client_id, arena))
+            .collect();
+        self.observe(
+            channel,
+            ReplayTarget::Arena(arena.0),
+            &message,
+            recipients.len(),
+        );
+        for client_id in recipients {
+            self.server
+                .send_message(client_id, channel, message.clone());
+        }
+    }
+
+    // everything that watches outgoing traffic sees the payload once, before it is sent
+    fn observe(&mut self, channel: u8, target: ReplayTarget, message: &[u8], recipients: usize) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(channel, target, message);
+        }
+        if let Some(metrics) = self.metrics.as_mut() {
+            metrics.count_sent(channel, message.len(), recipients);
+        }
+        if let Some(debug) = self.net_debug.as_mut() {
+            let decode = debug.visible;
+            debug.stats.record(channel, message, recipients, decode);
+        }
+    }
+
+    pub fn arenas(&self) -> &Arenas {
+        &self.arenas
+    }
+
+    pub fn arenas_mut(&mut self) -> &mut Arenas {
+        &mut self.arenas
+    }
+}
+
+impl<'w, 's> Deref for ServerSender<'w, 's> {
+    type Target = RenetServer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.server
+    }
+}
+
+impl<'w, 's> DerefMut for ServerSender<'w, 's> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.server
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn server_update_system(
+    mut server_events: EventReader<ServerEvent>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut lobby: ResMut<ServerLobby>,
+    mut server: ServerSender,
+    players: Query<(Entity, &Player, &Transform, Option<&Team>, &Arena)>,
+    npc_cells: Query<(Entity, &Cell, &Transform, &Arena), With<NpcCell>>,
+    cells: Query<(&Transform, &Cell, &Arena)>,
+    spawn_placement: Res<SpawnPlacement>,
+    mut rng: ResMut<ServerRng>,
+    settings: Res<ServerSettings>,
+    bans: Res<BanList>,
+    mut refusals: ResMut<Refusals>,
+    mut anti_cheat: ResMut<AntiCheat>,
+    mut sessions: ResMut<Sessions>,
+) {
+    let occupied = |arena: Arena| -> Vec<(Vec3, f32)> {
+        cells
+            .iter()
+            .filter(|(_, _, cell_arena)| **cell_arena == arena)
+            .map(|(transform, cell, _)| (transform.translation, cell.size))
+            .collect()
+    };
+
+    for event in server_events.iter() {
+        match event {
+            ServerEvent::ClientConnected(id, user_data) => {
+                let _span = info_span!("client", id = *id).entered();
+                let ip = server.client_addr(*id).map(|addr| addr.ip());
+                let others = other_addresses(&server, &refusals, *id);
+                if let Err(reason) = check_connection(&bans, &settings, *id, ip, &others) {
+                    refusals.refuse(&mut server, *id, reason);
+                    continue;
+                }
+                let connection_data = ConnectionData::from_user_data(user_data);
+                let resumed = match sessions.resume(*id, connection_data.session) {
+                    Ok(resumed) => resumed,
+                    Err(reason) => {
+                        refusals.refuse(&mut server, *id, reason);
+                        continue;
+                    }
+                };
+                let message = bincode::serialize(&ServerMessages::Session {
+                    token: sessions.token(*id),
+                })
+                .unwrap();
+                server.send_message(*id, ServerChannel::ServerMessages, message);
+
+                // a resumed client is still in the arena it dropped from
+                let arena = if resumed {
+                    server.arenas().arena_of(*id)
+                } else {
+                    server.arenas_mut().join(*id, &connection_data.room)
+                };
+                info!(
+                    name = connection_data.name.as_str(),
+                    resumed,
+                    "Connected to {}.",
+                    server.arenas().settings(arena).code
+                );
+
+                // Initialize other players for this new client
+                for (entity, player, transform, team, _) in players
+                    .iter()
+                    .filter(|(_, _, _, _, player_arena)| **player_arena == arena)
+                {
+                    let translation: [f32; 3] = transform.translation.into();
+                    let message = bincode::serialize(&ServerMessages::PlayerCreate {
+                        id: player.id,
+                        entity,
+                        translation,
+                        team: team.copied(),
+                    })
+                    .unwrap();
+                    server.send_message(*id, ServerChannel::ServerMessages, message);
+
+                    // players are created at the starting size, a resumed cell included
+                    if let Ok((_, cell, _)) = cells.get(entity) {
+                        let message = bincode::serialize(&ServerMessages::UpdateEntityCell {
+                            entity,
+                            size: cell.size,
+                        })
+                        .unwrap();
+                        server.send_message(*id, ServerChannel::ServerMessages, message);
+                    }
+                }
+
+                // initialize npc cells already spawned
+                for (entity, cell, transform, _) in npc_cells
+                    .iter()
+                    .filter(|(_, _, _, cell_arena)| **cell_arena == arena)
+                {
+                    let translation: [f32; 3] = transform.translation.into();
+                    let message = bincode::serialize(&ServerMessages::SpawnNpcCell {
+                        entity,
+                        size: cell.size,
+                        translation,
+                    })
+                    .unwrap();
+                    server.send_message(*id, ServerChannel::ServerMessages, message);
+                }
+
+                if resumed && lobby.players.contains_key(id) {
+                    info!("Resumed session.");
+                    continue;
+                }
+                lobby.names.insert(*id, connection_data.player_name(*id));
+                if connection_data.spectator {
+                    info!("Spectating.");
+                    lobby.spectators.insert(*id);
+                    continue;
+                }
+                assign_team(&mut lobby, *id, settings.teams);
+                spawn_player(
+                    &mut commands,
+                    &mut meshes,
+                    &mut materials,
+                    &mut lobby,
+                    &mut server,
+                    &mut rng,
+                    &spawn_placement,
+                    &occupied(arena),
+                    *id,
+                    arena,
+                );
+            }
+            ServerEvent::ClientDisconnected(id) => {
+                let _span = info_span!("client", id = *id).entered();
+                // kicked clients don't get to come back, nor do the ones that quit
+                let kicked = refusals.take_disconnected(*id);
+                let left = sessions.take_leaving(*id);
+                if sessions.is_suspended(*id) {
+                    // a refused attempt to take over a dropped client's cell
+                    continue;
+                }
+                let player_entity = lobby.players.get(id).copied();
+                if let (Some(grace), Some(player_entity), false) =
+                    (settings.reconnect_grace, player_entity, kicked || left)
+                {
+                    info!("Lost connection, keeping the cell for {}s.", grace);
+                    sessions.suspend(*id, grace);
+                    // frozen in place until the client is back
+                    commands
+                        .entity(player_entity)
+                        .insert(PlayerInput::default());
+                    continue;
+                }
+                info!(left, "Disconnected.");
+                sessions.end(*id);
+                remove_client(&mut commands, &mut lobby, &mut server, *id);
+            }
+        }
+    }
+
+    for client_id in server.clients_id().into_iter() {
+        let _span = info_span!("client", id = client_id).entered();
+        while let Some(message) = server.receive_message(client_id, ClientChannel::Command) {
+            let command: PlayerCommand = match decode(&message) {
+                Ok(command) => command,
+                Err(violation) => {
+                    anti_cheat.report(client_id, violation);
+                    continue;
+                }
+            };
+            if !anti_cheat.allow(client_id, MessageKind::from(&command)) {
+                continue;
+            }
+            let origin = lobby
+                .players
+                .get(&client_id)
+                .and_then(|entity| players.get(*entity).ok())
+                .map(|(_, _, transform, _, _)| transform.translation);
+            let command = match validate_command(command, origin) {
+                Ok(command) => command,
+                Err(violation) => {
+                    anti_cheat.report(client_id, violation);
+                    continue;
+                }
+            };
+            match command {
+                PlayerCommand::BasicAttack { mut cast_at } => {
+                    debug!(?cast_at, "Basic attack.");
+
+                    if let Some(player_entity) = lobby.players.get(&client_id) {
+                        if let Ok((_, _, player_transform, _, _)) = players.get(*player_entity) {
+                            cast_at[1] = player_transform.translation[1];
+
+                            let direction =
+                                (cast_at - player_transform.translation).normalize_or_zero();
+                            let mut translation = player_transform.translation + (direction * 0.7);
+                            translation[1] = 1.0;
+                        }
+                    }
+                }
+                PlayerCommand::Respawn => {
+                    // refused clients never got a name
+                    if lobby.names.contains_key(&client_id)
+                        && !lobby.players.contains_key(&client_id)
+                        && !lobby.spectators.contains(&client_id)
+                    {
+                        info!("Respawned.");
+                        let arena = server.arenas().arena_of(client_id);
+                        spawn_player(
+                            &mut commands,
+                            &mut meshes,
+                            &mut materials,
+                            &mut lobby,
+                            &mut server,
+                            &mut rng,
+                            &spawn_placement,
+                            &occupied(arena),
+                            client_id,
+                            arena,
+                        );
+                    }
+                }
+                PlayerCommand::Leave => {
+                    info!("Leaving.");
+                    // the client doesn't wait for this, its own disconnect could lose the command
+                    sessions.leave(client_id);
+                    server.disconnect(client_id);
+                }
+            }
+        }
+        while let Some(message) = server.receive_message(client_id, ClientChannel::Input) {
+            let input: PlayerInput = match decode(&message) {
+                Ok(input) => input,
+                Err(violation) => {
+                    anti_cheat.report(client_id, violation);
+                    continue;
+                }
+            };
+            if !anti_cheat.allow(client_id, MessageKind::Input) {
+                continue;
+            }
+            if let Some(player_entity) = lobby.players.get(&client_id) {
+                commands.entity(*player_entity).insert(input);
+            }
+        }
+    }
+}
+
+/// Despawns the client's cell and forgets everything the lobby and arenas knew about it.
+pub fn remove_client(
+    commands: &mut Commands,
+    lobby: &mut ServerLobby,
+    server: &mut ServerSender,
+    client_id: u64,
+) {
+    let arena = server.arenas().arena_of(client_id);
+    if let Some(player_entity) = lobby.players.remove(&client_id) {
+        commands.entity(player_entity).despawn();
+    }
+    lobby.names.remove(&client_id);
+    lobby.spectators.remove(&client_id);
+    lobby.teams.remove(&client_id);
+
+    let message = bincode::serialize(&ServerMessages::PlayerRemove { id: client_id }).unwrap();
+    server.broadcast_in_arena(arena, ServerChannel::ServerMessages, message);
+    server.arenas_mut().leave(client_id);
+}
+
+pub fn move_players_system(mut query: Query<(&mut Transform, &PlayerInput)>, time: Res<Time>) {
+    for (mut transform, input) in query.iter_mut() {
+        let x = (input.right as i8 - input.left as i8) as f32;
+        let y = (input.down as i8 - input.up as i8) as f32;
+        let direction = Vec2::new(x, y).normalize_or_zero();
+
+        transform.translation +=
+            Vec3::new(direction.x, 0.0, direction.y) * PLAYER_MOVE_SPEED * time.delta_seconds();
+    }
+}
+
+#[allow(clippy::type_complexity)]
+pub fn server_network_sync(
+    mut server: ServerSender,
+    query: Query<(Entity, &Transform, &Arena), With<Player>>,
+) {
+    let arenas: Vec<Arena> = server.arenas().iter().map(|(arena, _)| arena).collect();
+    for arena in arenas {
+        let mut networked_entities = NetworkedEntities::default();
+        for (entity, transform, _) in query
+            .iter()
+            .filter(|(_, _, player_arena)| **player_arena == arena)
+        {
+            networked_entities.entities.push(entity);
+            networked_entities
+                .translations
+                .push(transform.translation.into());
+            networked_entities.scalings.push(transform.scale.into());
+        }
+
+        let sync_message = bincode::serialize(&networked_entities).unwrap();
+        server.broadcast_in_arena(arena, ServerChannel::NetworkedEntities, sync_message);
+    }
+}
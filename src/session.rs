@@ -0,0 +1,263 @@
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::SystemTime,
+};
+
+use bevy::{
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
+use bevy_egui::{egui, EguiContext};
+use bevy_renet::renet::{ClientAuthentication, RenetClient};
+
+use crate::{
+    client::{ClientLobby, NetworkMapping},
+    client_connection_config,
+    server::{remove_client, ServerSender},
+    ConnectionData, ServerLobby, PROTOCOL_ID,
+};
+
+pub const DEFAULT_RECONNECT_GRACE: f32 = 20.0;
+// seconds between connection attempts while reconnecting
+pub const RECONNECT_INTERVAL: f32 = 1.0;
+// the client gives up a little after the server's default grace period
+pub const RECONNECT_TIMEOUT: f32 = 30.0;
+
+/// Sent on the server when a dropped client didn't come back in time.
+#[derive(Debug, Clone)]
+pub struct SessionExpired {
+    pub client_id: u64,
+}
+
+/// Session tokens of connected clients, and the dropped ones whose cell is kept for them.
+///
+/// A client that loses its link keeps its cell, frozen in place, for the reconnect
+/// grace period. Connecting again with the same id and token gives it back. One that
+/// sent `PlayerCommand::Leave` quit on purpose and loses its cell right away.
+#[derive(Debug, Default, Resource)]
+pub struct Sessions {
+    tokens: HashMap<u64, u64>,
+    suspended: HashMap<u64, Timer>,
+    leaving: HashSet<u64>,
+}
+
+impl Sessions {
+    /// The client's token, a fresh one unless it is resuming.
+    pub fn token(&mut self, client_id: u64) -> u64 {
+        // 0 means no session
+        *self
+            .tokens
+            .entry(client_id)
+            .or_insert_with(|| rand::random::<u64>().max(1))
+    }
+
+    /// Whether the client picks up a suspended session, refusing it when the token is wrong.
+    pub fn resume(&mut self, client_id: u64, token: u64) -> Result<bool, &'static str> {
+        if !self.suspended.contains_key(&client_id) {
+            // whatever the client had before is gone
+            self.tokens.remove(&client_id);
+            return Ok(false);
+        }
+        if self.tokens.get(&client_id) != Some(&token) {
+            return Err("This player is reconnecting from elsewhere.");
+        }
+        self.suspended.remove(&client_id);
+        Ok(true)
+    }
+
+    pub fn suspend(&mut self, client_id: u64, grace: f32) {
+        self.suspended
+            .insert(client_id, Timer::from_seconds(grace, TimerMode::Once));
+    }
+
+    pub fn is_suspended(&self, client_id: u64) -> bool {
+        self.suspended.contains_key(&client_id)
+    }
+
+    /// Marks the client as quitting, so its disconnect doesn't keep the cell.
+    pub fn leave(&mut self, client_id: u64) {
+        self.leaving.insert(client_id);
+    }
+
+    /// Whether the client asked to leave, forgetting it afterwards.
+    pub fn take_leaving(&mut self, client_id: u64) -> bool {
+        self.leaving.remove(&client_id)
+    }
+
+    pub fn end(&mut self, client_id: u64) {
+        self.tokens.remove(&client_id);
+        self.suspended.remove(&client_id);
+        self.leaving.remove(&client_id);
+    }
+}
+
+/// Removes the cells of clients that didn't reconnect within the grace period.
+pub fn expire_sessions(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut sessions: ResMut<Sessions>,
+    mut lobby: ResMut<ServerLobby>,
+    mut server: ServerSender,
+    mut expired: EventWriter<SessionExpired>,
+) {
+    let mut ended = Vec::new();
+    for (client_id, timer) in sessions.suspended.iter_mut() {
+        if timer.tick(time.delta()).finished() {
+            ended.push(*client_id);
+        }
+    }
+    for client_id in ended {
+        let _span = info_span!("client", id = client_id).entered();
+        info!("Did not reconnect in time.");
+        sessions.end(client_id);
+        remove_client(&mut commands, &mut lobby, &mut server, client_id);
+        expired.send(SessionExpired { client_id });
+    }
+}
+
+#[derive(Debug)]
+struct Reconnecting {
+    elapsed: f32,
+    retry_timer: Timer,
+}
+
+/// What the client needs to connect again with the same id and session token.
+#[derive(Debug, Resource)]
+pub struct ClientSession {
+    pub server_addr: SocketAddr,
+    pub client_id: u64,
+    // `session` is filled in once the server hands out a token
+    pub connection_data: ConnectionData,
+    // the server said why it won't let us play, no point in retrying then
+    pub refused: bool,
+    // only a session that was connected once has a cell to go back to
+    connected: bool,
+    // the first connection attempt failed
+    unreachable: bool,
+    reconnecting: Option<Reconnecting>,
+}
+
+impl ClientSession {
+    pub fn new(server_addr: SocketAddr, client_id: u64, connection_data: ConnectionData) -> Self {
+        Self {
+            server_addr,
+            client_id,
+            connection_data,
+            refused: false,
+            connected: false,
+            unreachable: false,
+            reconnecting: None,
+        }
+    }
+
+    pub fn renet_client(&self) -> RenetClient {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let current_time = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+        let authentication = ClientAuthentication::Unsecure {
+            client_id: self.client_id,
+            protocol_id: PROTOCOL_ID,
+            server_addr: self.server_addr,
+            user_data: Some(self.connection_data.to_user_data()),
+        };
+        RenetClient::new(
+            current_time,
+            socket,
+            client_connection_config(),
+            authentication,
+        )
+        .unwrap()
+    }
+
+    pub fn is_reconnecting(&self) -> bool {
+        self.reconnecting.is_some()
+    }
+
+    pub fn is_unreachable(&self) -> bool {
+        self.unreachable
+    }
+}
+
+/// Connects again after the link drops, starting from an empty world the server fills back in.
+pub fn client_reconnect(
+    time: Res<Time>,
+    mut commands: Commands,
+    mut session: ResMut<ClientSession>,
+    mut client: ResMut<RenetClient>,
+    mut lobby: ResMut<ClientLobby>,
+    mut network_mapping: ResMut<NetworkMapping>,
+) {
+    if client.is_connected() {
+        if session.is_reconnecting() {
+            info!("Reconnected.");
+            session.reconnecting = None;
+        }
+        session.connected = true;
+        return;
+    }
+    if session.refused || session.unreachable {
+        return;
+    }
+
+    if !session.is_reconnecting() {
+        // still on the first connection attempt
+        if client.disconnected().is_none() {
+            return;
+        }
+        if !session.connected {
+            warn!("Could not reach the server.");
+            session.unreachable = true;
+            return;
+        }
+        warn!("Lost connection to the server, reconnecting.");
+        for (_, entity) in network_mapping.0.drain() {
+            commands.entity(entity).despawn();
+        }
+        lobby.players.clear();
+        // the first attempt waits an interval too, the old client still says goodbye
+        session.reconnecting = Some(Reconnecting {
+            elapsed: 0.0,
+            retry_timer: Timer::from_seconds(RECONNECT_INTERVAL, TimerMode::Repeating),
+        });
+        return;
+    }
+
+    let retry = match session.reconnecting.as_mut() {
+        Some(reconnecting) => {
+            reconnecting.elapsed += time.delta_seconds();
+            let retry = reconnecting.retry_timer.tick(time.delta()).just_finished();
+            retry && reconnecting.elapsed < RECONNECT_TIMEOUT
+        }
+        None => false,
+    };
+    // renet keeps sending connection requests until it times out on its own
+    if retry && client.disconnected().is_some() {
+        *client = session.renet_client();
+    }
+}
+
+pub fn reconnecting_overlay(mut egui_context: ResMut<EguiContext>, session: Res<ClientSession>) {
+    if !session.unreachable && session.reconnecting.is_none() {
+        return;
+    }
+    egui::Window::new("Connection")
+        .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+        .title_bar(false)
+        .resizable(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            let reconnecting = match &session.reconnecting {
+                Some(reconnecting) => reconnecting,
+                None => {
+                    ui.heading("Could not reach the server.");
+                    return;
+                }
+            };
+            if reconnecting.elapsed < RECONNECT_TIMEOUT {
+                ui.heading("Reconnecting…");
+                ui.label(format!("{}s", reconnecting.elapsed as u32));
+            } else {
+                ui.heading("Connection lost.");
+            }
+        });
+}
@@ -0,0 +1,169 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use bevy::prelude::*;
+
+use crate::{
+    arena::ArenaSettings, logging::LogSettings, session::DEFAULT_RECONNECT_GRACE, team::MAX_TEAMS,
+    MAX_ROOM_CODE_LENGTH,
+};
+
+pub const DEFAULT_MAX_CONNECTIONS_PER_IP: usize = 4;
+pub const DEFAULT_MAX_CLIENTS: usize = 64;
+
+/// Server configuration, read from the command line at startup.
+///
+/// usage: server [--rooms <code[:round seconds],...>] [--bots <count>] [--teams <count>]
+///               [--round <seconds>] [--seed <seed>] [--record <dir>] [--chat-range <units>]
+///               [--chat-blocklist <file>] [--ban-list <file>] [--max-per-ip <count>]
+///               [--max-clients <count>] [--metrics <addr:port>]
+///               [--profiles <file>] [--reconnect-grace <seconds>] [--log <filter>] [--log-json]
+#[derive(Debug, Clone, Default, Resource)]
+pub struct ServerSettings {
+    // one arena per room code, a single "main" arena when empty
+    pub rooms: Vec<ArenaSettings>,
+    pub bots: usize,
+    // free-for-all when 0
+    pub teams: usize,
+    // round length in seconds for rooms without their own, endless when not set
+    pub round_duration: Option<f32>,
+    // picked at random on startup when not set
+    pub seed: Option<u64>,
+    // replays are written here when set
+    pub record_dir: Option<PathBuf>,
+    // chat only reaches players this close to the sender when set
+    pub chat_range: Option<f32>,
+    // one blocked word per line
+    pub chat_blocklist: Option<PathBuf>,
+    // banned ids and addresses, kept in sync with the admin console
+    pub ban_list: Option<PathBuf>,
+    // unlimited when not set, `--max-per-ip 0` turns the default limit off
+    pub max_connections_per_ip: Option<usize>,
+    // connection slots renet is created with
+    pub max_clients: usize,
+    // Prometheus metrics are served at http://<addr>/metrics when set
+    pub metrics_addr: Option<SocketAddr>,
+    // lifetime player stats are kept in this file when set
+    pub profiles: Option<PathBuf>,
+    // seconds a dropped client's cell waits for it, `--reconnect-grace 0` removes it right away
+    pub reconnect_grace: Option<f32>,
+    pub log: LogSettings,
+    // arguments that were ignored and why, logged once logging is set up
+    pub warnings: Vec<String>,
+}
+
+impl ServerSettings {
+    pub fn from_args(args: impl Iterator<Item = String>) -> Self {
+        let mut settings = Self {
+            max_connections_per_ip: Some(DEFAULT_MAX_CONNECTIONS_PER_IP),
+            max_clients: DEFAULT_MAX_CLIENTS,
+            reconnect_grace: Some(DEFAULT_RECONNECT_GRACE),
+            ..Default::default()
+        };
+        let mut args = args.skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--rooms" => {
+                    if let Some(rooms) = args.next() {
+                        settings.rooms = rooms
+                            .split(',')
+                            .filter_map(|room| parse_room(room, &mut settings.warnings))
+                            .collect();
+                    }
+                }
+                "--bots" => {
+                    if let Some(bots) = args.next().and_then(|value| value.parse().ok()) {
+                        settings.bots = bots;
+                    }
+                }
+                "--teams" => {
+                    if let Some(teams) = args.next().and_then(|value| value.parse().ok()) {
+                        settings.teams = usize::min(teams, MAX_TEAMS);
+                    }
+                }
+                "--round" => {
+                    settings.round_duration = seconds(&arg, args.next(), &mut settings.warnings);
+                }
+                "--seed" => {
+                    settings.seed = args.next().and_then(|value| value.parse().ok());
+                }
+                "--record" => {
+                    settings.record_dir = args.next().map(PathBuf::from);
+                }
+                "--chat-range" => {
+                    settings.chat_range = args.next().and_then(|value| value.parse().ok());
+                }
+                "--chat-blocklist" => {
+                    settings.chat_blocklist = args.next().map(PathBuf::from);
+                }
+                "--ban-list" => {
+                    settings.ban_list = args.next().map(PathBuf::from);
+                }
+                "--max-per-ip" => {
+                    if let Some(limit) = args.next().and_then(|value| value.parse().ok()) {
+                        settings.max_connections_per_ip = Some(limit).filter(|limit| *limit > 0);
+                    }
+                }
+                "--max-clients" => {
+                    if let Some(limit) = args.next().and_then(|value| value.parse().ok()) {
+                        settings.max_clients = usize::max(limit, 1);
+                    }
+                }
+                "--metrics" => {
+                    settings.metrics_addr = args.next().and_then(|value| value.parse().ok());
+                }
+                "--profiles" => {
+                    settings.profiles = args.next().map(PathBuf::from);
+                }
+                "--reconnect-grace" => {
+                    let value = args.next();
+                    // 0 removes dropped players right away
+                    if value.as_deref().and_then(|value| value.parse::<f32>().ok()) == Some(0.0) {
+                        settings.reconnect_grace = None;
+                    } else if let Some(grace) = seconds(&arg, value, &mut settings.warnings) {
+                        settings.reconnect_grace = Some(grace);
+                    }
+                }
+                _ if settings.log.parse_arg(&arg, &mut args) => {}
+                _ => settings
+                    .warnings
+                    .push(format!("Ignoring unknown argument {}.", arg)),
+            }
+        }
+        settings
+    }
+}
+
+// `code` or `code:seconds`, the latter plays rounds of that length in the room
+fn parse_room(room: &str, warnings: &mut Vec<String>) -> Option<ArenaSettings> {
+    let (code, round) = match room.split_once(':') {
+        Some((code, round)) => (
+            code,
+            seconds("--rooms", Some(round.trim().to_string()), warnings),
+        ),
+        None => (room, None),
+    };
+    // clients can't send a longer code
+    let code: String = code.trim().chars().take(MAX_ROOM_CODE_LENGTH).collect();
+    if code.is_empty() {
+        return None;
+    }
+    let mut settings = ArenaSettings::new(&code);
+    settings.round_duration = round;
+    Some(settings)
+}
+
+// a finite number of seconds above zero, anything else would panic in a `Timer`
+fn seconds(arg: &str, value: Option<String>, warnings: &mut Vec<String>) -> Option<f32> {
+    let seconds = value
+        .as_deref()
+        .and_then(|value| value.parse::<f32>().ok())
+        .filter(|seconds| seconds.is_finite() && *seconds > 0.0);
+    if seconds.is_none() {
+        warnings.push(format!(
+            "Ignoring {} {}, it takes a number of seconds above 0.",
+            arg,
+            value.unwrap_or_default()
+        ));
+    }
+    seconds
+}
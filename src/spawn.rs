@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+use rand::Rng;
+
+use crate::FIELD_SIZE;
+
+// cells at or above this size can eat a freshly spawned player
+pub const THREAT_MASS: f32 = 1.5;
+pub const SAFE_SPAWN_DISTANCE: f32 = 20.0;
+pub const FOOD_MIN_DISTANCE: f32 = 2.0;
+pub const SPAWN_ATTEMPTS: usize = 16;
+
+/// Picks spawn points for players and food across the whole field.
+///
+/// Candidates are sampled uniformly and rejected when they land too close to
+/// existing cells. Every lookup takes the occupied cells as `(translation, size)`
+/// pairs so it can be fed from any query.
+#[derive(Debug, Clone, Resource)]
+pub struct SpawnPlacement {
+    pub threat_mass: f32,
+    pub safe_distance: f32,
+    pub food_distance: f32,
+    pub attempts: usize,
+}
+
+impl Default for SpawnPlacement {
+    fn default() -> Self {
+        Self {
+            threat_mass: THREAT_MASS,
+            safe_distance: SAFE_SPAWN_DISTANCE,
+            food_distance: FOOD_MIN_DISTANCE,
+            attempts: SPAWN_ATTEMPTS,
+        }
+    }
+}
+
+impl SpawnPlacement {
+    /// Finds a point away from every cell above the threat mass.
+    ///
+    /// When the field is too crowded to find a safe point, the candidate furthest
+    /// from any threat is used instead, so a player always gets a cell.
+    pub fn player_spawn_point(&self, rng: &mut impl Rng, cells: &[(Vec3, f32)]) -> Vec3 {
+        let mut best = random_field_point(rng);
+        let mut best_clearance = f32::MIN;
+
+        for _ in 0..self.attempts {
+            let candidate = random_field_point(rng);
+            let clearance = cells
+                .iter()
+                .filter(|(_, size)| *size >= self.threat_mass)
+                .map(|(translation, size)| {
+                    flat_distance(candidate, *translation) - size - self.safe_distance
+                })
+                .fold(f32::MAX, f32::min);
+
+            if clearance >= 0.0 {
+                return candidate;
+            }
+            if clearance > best_clearance {
+                best = candidate;
+                best_clearance = clearance;
+            }
+        }
+
+        best
+    }
+
+    /// Finds a point that keeps the minimum distance from every existing cell.
+    ///
+    /// Returns `None` when no candidate fits, in which case the caller should
+    /// skip this spawn and try again later.
+    pub fn food_spawn_point(&self, rng: &mut impl Rng, cells: &[(Vec3, f32)]) -> Option<Vec3> {
+        (0..self.attempts)
+            .map(|_| random_field_point(rng))
+            .find(|candidate| {
+                cells.iter().all(|(translation, size)| {
+                    flat_distance(*candidate, *translation) >= size + self.food_distance
+                })
+            })
+    }
+}
+
+pub fn random_field_point(rng: &mut impl Rng) -> Vec3 {
+    let x = rng.gen_range(-FIELD_SIZE / 2.0..FIELD_SIZE / 2.0);
+    let z = rng.gen_range(-FIELD_SIZE / 2.0..FIELD_SIZE / 2.0);
+    Vec3::new(x, 0.0, z)
+}
+
+// cells live on the floor plane, height doesn't matter for spacing
+fn flat_distance(a: Vec3, b: Vec3) -> f32 {
+    Vec2::new(a.x - b.x, a.z - b.z).length()
+}
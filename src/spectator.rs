@@ -0,0 +1,112 @@
+use bevy::prelude::*;
+use bevy_egui::{egui, EguiContext};
+
+use crate::{client::ClientLobby, leaderboard::ClientLeaderboard, GameState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SpectatorFocus {
+    #[default]
+    Leader,
+    Player(u64),
+}
+
+/// Who a spectating client is watching, read by `camera_follow`.
+#[derive(Debug, Default, Resource)]
+pub struct Spectator {
+    pub focus: SpectatorFocus,
+    // resolved every frame, `None` while nobody is in the arena
+    pub following: Option<(u64, Entity)>,
+}
+
+/// Camera and UI for clients that connected with `ConnectionData::spectator`.
+pub struct SpectatorPlugin;
+
+impl Plugin for SpectatorPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Spectator>().add_system_set(
+            SystemSet::on_update(GameState::InGame)
+                .with_system(cycle_spectator_focus)
+                .with_system(update_spectator_target.after(cycle_spectator_focus))
+                .with_system(spectator_window.after(update_spectator_target)),
+        );
+    }
+}
+
+/// Tab moves to the next player by id, L goes back to following the leader.
+fn cycle_spectator_focus(
+    keyboard_input: Res<Input<KeyCode>>,
+    mut spectator: ResMut<Spectator>,
+    lobby: Res<ClientLobby>,
+) {
+    if keyboard_input.just_pressed(KeyCode::L) {
+        spectator.focus = SpectatorFocus::Leader;
+    }
+    if !keyboard_input.just_pressed(KeyCode::Tab) {
+        return;
+    }
+
+    let mut ids: Vec<u64> = lobby.players.keys().copied().collect();
+    if ids.is_empty() {
+        return;
+    }
+    ids.sort_unstable();
+    let current = spectator.following.map(|(id, _)| id);
+    let next = match current.and_then(|current| ids.iter().position(|id| *id == current)) {
+        Some(index) => ids[(index + 1) % ids.len()],
+        None => ids[0],
+    };
+    spectator.focus = SpectatorFocus::Player(next);
+}
+
+fn update_spectator_target(
+    mut spectator: ResMut<Spectator>,
+    lobby: Res<ClientLobby>,
+    leaderboard: Res<ClientLeaderboard>,
+) {
+    let leader = leaderboard.entries.first().map(|entry| entry.id);
+    let id = match spectator.focus {
+        SpectatorFocus::Player(id) if lobby.players.contains_key(&id) => Some(id),
+        // the chosen player was eaten or left, fall back to the leader
+        SpectatorFocus::Player(_) => {
+            spectator.focus = SpectatorFocus::Leader;
+            leader
+        }
+        SpectatorFocus::Leader => leader,
+    };
+
+    spectator.following = id.and_then(|id| {
+        lobby
+            .players
+            .get(&id)
+            .map(|player_info| (id, player_info.client_entity))
+    });
+}
+
+fn spectator_window(
+    mut egui_context: ResMut<EguiContext>,
+    spectator: Res<Spectator>,
+    leaderboard: Res<ClientLeaderboard>,
+) {
+    let following = match spectator.following {
+        Some((id, _)) => leaderboard
+            .entries
+            .iter()
+            .find(|entry| entry.id == id)
+            .map(|entry| entry.name.clone())
+            .unwrap_or_else(|| format!("Player {}", id % 10000)),
+        None => "nobody".to_string(),
+    };
+    let mode = match spectator.focus {
+        SpectatorFocus::Leader => "leader",
+        SpectatorFocus::Player(_) => "chosen player",
+    };
+
+    egui::Window::new("Spectating")
+        .anchor(egui::Align2::CENTER_TOP, [0.0, 10.0])
+        .resizable(false)
+        .collapsible(false)
+        .show(egui_context.ctx_mut(), |ui| {
+            ui.label(format!("Following {} ({})", following, mode));
+            ui.label("Tab: next player   L: follow the leader");
+        });
+}
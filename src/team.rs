@@ -0,0 +1,73 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::ServerLobby;
+
+pub const MAX_TEAMS: usize = 4;
+pub const TEAM_COLORS: [Color; MAX_TEAMS] = [
+    Color::rgb(0.9, 0.25, 0.25),
+    Color::rgb(0.25, 0.45, 0.95),
+    Color::rgb(0.25, 0.8, 0.3),
+    Color::rgb(0.95, 0.8, 0.2),
+];
+// players without a team keep the original colour
+pub const NO_TEAM_COLOR: Color = Color::rgb(0.5, 0.5, 1.0);
+
+/// Players on the same team can't eat each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, Component)]
+pub struct Team(pub u8);
+
+impl Team {
+    pub fn color(self) -> Color {
+        TEAM_COLORS[self.0 as usize % MAX_TEAMS]
+    }
+
+    pub fn name(self) -> &'static str {
+        ["Red", "Blue", "Green", "Yellow"][self.0 as usize % MAX_TEAMS]
+    }
+}
+
+pub fn team_color(team: Option<Team>) -> Color {
+    team.map(Team::color).unwrap_or(NO_TEAM_COLOR)
+}
+
+/// Name of the colour `team_color` picks, as kept in player profiles.
+///
+/// `None` without a team, every cell in free-for-all has the same colour.
+pub fn team_color_name(team: Option<Team>) -> Option<&'static str> {
+    team.map(Team::name)
+}
+
+/// Puts `id` on the team with the fewest members, `None` in free-for-all.
+///
+/// The team is kept in the lobby so a respawn lands on the same side.
+pub fn assign_team(lobby: &mut ServerLobby, id: u64, teams: usize) -> Option<Team> {
+    if teams == 0 {
+        return None;
+    }
+    if let Some(team) = lobby.teams.get(&id) {
+        return Some(*team);
+    }
+
+    let mut members = vec![0; teams];
+    for team in lobby.teams.values() {
+        if let Some(count) = members.get_mut(team.0 as usize) {
+            *count += 1;
+        }
+    }
+    let smallest = (0..teams).min_by_key(|team| members[*team]).unwrap_or(0);
+    let team = Team(smallest as u8);
+    lobby.teams.insert(id, team);
+    Some(team)
+}
+
+/// Sums mass per team, indexed by team number.
+pub fn team_masses(players: impl Iterator<Item = (Team, f32)>, teams: usize) -> Vec<f32> {
+    let mut masses = vec![0.0; teams];
+    for (team, mass) in players {
+        if let Some(total) = masses.get_mut(team.0 as usize) {
+            *total += mass;
+        }
+    }
+    masses
+}
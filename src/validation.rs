@@ -0,0 +1,175 @@
+use bevy::{prelude::*, utils::HashMap};
+use bincode::Options;
+use serde::de::DeserializeOwned;
+
+use crate::{access::Refusals, server::ServerSender, PlayerCommand};
+
+// nothing a well behaved client sends comes close, chat lines included
+pub const MAX_CLIENT_MESSAGE_SIZE: u64 = 1024;
+// a client past this score is kicked
+pub const MAX_VIOLATION_SCORE: f32 = 10.0;
+// points forgiven per second
+pub const VIOLATION_DECAY: f32 = 1.0;
+// how far from its cell a player can aim
+pub const MAX_CAST_RANGE: f32 = 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    // didn't decode, was too big or had trailing bytes
+    Malformed,
+    // NaN or infinite where a number was expected
+    NonFinite,
+    RateLimited,
+}
+
+impl Violation {
+    /// How much the violation adds to the client's score.
+    pub fn weight(self) -> f32 {
+        match self {
+            Violation::Malformed => 4.0,
+            Violation::NonFinite => 4.0,
+            // a busy frame on the client can legitimately burst past the limit
+            Violation::RateLimited => 0.25,
+        }
+    }
+}
+
+/// Client messages rate limited separately from each other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageKind {
+    Input,
+    Attack,
+    Respawn,
+    Leave,
+}
+
+impl MessageKind {
+    // (burst, refill per second)
+    fn rate(self) -> (f32, f32) {
+        match self {
+            // sent every client frame, high refresh rate screens included
+            MessageKind::Input => (60.0, 300.0),
+            MessageKind::Attack => (5.0, 5.0),
+            MessageKind::Respawn => (2.0, 1.0),
+            MessageKind::Leave => (1.0, 1.0),
+        }
+    }
+}
+
+impl From<&PlayerCommand> for MessageKind {
+    fn from(command: &PlayerCommand) -> Self {
+        match command {
+            PlayerCommand::BasicAttack { .. } => MessageKind::Attack,
+            PlayerCommand::Respawn => MessageKind::Respawn,
+            PlayerCommand::Leave => MessageKind::Leave,
+        }
+    }
+}
+
+/// Decodes a client payload, refusing oversized ones and trailing bytes.
+pub fn decode<T: DeserializeOwned>(message: &[u8]) -> Result<T, Violation> {
+    bincode::options()
+        .with_fixint_encoding()
+        .with_limit(MAX_CLIENT_MESSAGE_SIZE)
+        .reject_trailing_bytes()
+        .deserialize(message)
+        .map_err(|_| Violation::Malformed)
+}
+
+/// Rejects non-finite values and pulls aim past `MAX_CAST_RANGE` back towards `origin`.
+pub fn validate_command(
+    command: PlayerCommand,
+    origin: Option<Vec3>,
+) -> Result<PlayerCommand, Violation> {
+    match command {
+        PlayerCommand::BasicAttack { cast_at } => {
+            if !cast_at.is_finite() {
+                return Err(Violation::NonFinite);
+            }
+            let cast_at = match origin {
+                Some(origin) => origin + (cast_at - origin).clamp_length_max(MAX_CAST_RANGE),
+                None => cast_at,
+            };
+            Ok(PlayerCommand::BasicAttack { cast_at })
+        }
+        PlayerCommand::Respawn => Ok(PlayerCommand::Respawn),
+        PlayerCommand::Leave => Ok(PlayerCommand::Leave),
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClientRecord {
+    score: f32,
+    allowance: HashMap<MessageKind, f32>,
+}
+
+/// Per-client violation scores and message rate limits.
+///
+/// Systems reading client messages report what they reject here, and
+/// `enforce_violation_scores` kicks whoever goes past `MAX_VIOLATION_SCORE`.
+#[derive(Debug, Default, Resource)]
+pub struct AntiCheat {
+    clients: HashMap<u64, ClientRecord>,
+}
+
+impl AntiCheat {
+    /// Takes one message of `kind` from the client's allowance, reporting it when empty.
+    pub fn allow(&mut self, client_id: u64, kind: MessageKind) -> bool {
+        let record = self.clients.entry(client_id).or_default();
+        let allowance = record.allowance.entry(kind).or_insert(kind.rate().0);
+        if *allowance < 1.0 {
+            self.report(client_id, Violation::RateLimited);
+            return false;
+        }
+        *allowance -= 1.0;
+        true
+    }
+
+    pub fn report(&mut self, client_id: u64, violation: Violation) {
+        let record = self.clients.entry(client_id).or_default();
+        record.score += violation.weight();
+        if violation != Violation::RateLimited {
+            warn!(
+                client = client_id,
+                score = record.score,
+                "Rejected a {:?} message.",
+                violation
+            );
+        }
+    }
+
+    pub fn score(&self, client_id: u64) -> f32 {
+        self.clients
+            .get(&client_id)
+            .map(|record| record.score)
+            .unwrap_or_default()
+    }
+}
+
+pub fn enforce_violation_scores(
+    time: Res<Time>,
+    mut anti_cheat: ResMut<AntiCheat>,
+    mut refusals: ResMut<Refusals>,
+    mut server: ServerSender,
+) {
+    let delta = time.delta_seconds();
+    let clients = server.clients_id();
+    anti_cheat
+        .clients
+        .retain(|client_id, _| clients.contains(client_id));
+
+    let mut kicked = Vec::new();
+    for (client_id, record) in anti_cheat.clients.iter_mut() {
+        record.score = (record.score - VIOLATION_DECAY * delta).max(0.0);
+        for (kind, allowance) in record.allowance.iter_mut() {
+            let (burst, refill) = kind.rate();
+            *allowance = (*allowance + refill * delta).min(burst);
+        }
+        if record.score > MAX_VIOLATION_SCORE {
+            kicked.push(*client_id);
+        }
+    }
+    for client_id in kicked {
+        refusals.refuse(&mut server, client_id, "Kicked for sending invalid input.");
+    }
+}
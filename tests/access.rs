@@ -0,0 +1,120 @@
+use std::{
+    fs::{self, File},
+    net::IpAddr,
+    path::PathBuf,
+    time::{Duration, SystemTime},
+};
+
+use cagario::{
+    access::{check_connection, BanList, ClientTarget},
+    settings::ServerSettings,
+};
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cagario-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+fn ip(value: &str) -> IpAddr {
+    value.parse().unwrap()
+}
+
+#[test]
+fn ban_list_file_is_parsed() {
+    let path = temp_file("parsed.txt");
+    fs::write(
+        &path,
+        "# banned for spamming\n12345\n  10.0.0.1   # shared flat\n::1\n\nnot-an-entry\n",
+    )
+    .unwrap();
+
+    let bans = BanList::load(&path).unwrap();
+    assert_eq!(bans.len(), 3);
+    assert!(bans.is_banned(12345, None));
+    assert!(bans.is_banned(1, Some(ip("10.0.0.1"))));
+    assert!(bans.is_banned(1, Some(ip("::1"))));
+    assert!(!bans.is_banned(1, Some(ip("10.0.0.2"))));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn bans_are_written_back() {
+    let path = temp_file("written.txt");
+    let mut bans = BanList::load(&path).unwrap();
+    assert!(bans.is_empty());
+
+    bans.ban(ClientTarget::Id(7));
+    bans.ban(ClientTarget::Ip(ip("192.168.1.20")));
+    bans.ban(ClientTarget::Id(8));
+    assert!(bans.unban(ClientTarget::Id(8)));
+    assert!(!bans.unban(ClientTarget::Id(9)));
+
+    let reloaded = BanList::load(&path).unwrap();
+    assert_eq!(reloaded.len(), 2);
+    assert!(reloaded.is_banned(7, None));
+    assert!(reloaded.is_banned(1, Some(ip("192.168.1.20"))));
+    assert!(!reloaded.is_banned(8, None));
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn hand_edits_are_reloaded() {
+    let path = temp_file("edited.txt");
+    fs::write(&path, "7\n").unwrap();
+    let mut bans = BanList::load(&path).unwrap();
+    assert!(!bans.reload_if_changed().unwrap());
+
+    fs::write(&path, "7\n10.0.0.1\n").unwrap();
+    // some filesystems only keep whole seconds
+    File::options()
+        .write(true)
+        .open(&path)
+        .unwrap()
+        .set_modified(SystemTime::now() + Duration::from_secs(60))
+        .unwrap();
+    assert!(bans.reload_if_changed().unwrap());
+    assert!(bans.is_banned(1, Some(ip("10.0.0.1"))));
+    assert!(!bans.reload_if_changed().unwrap());
+
+    // our own writes are not picked up as edits
+    bans.ban(ClientTarget::Id(8));
+    assert!(!bans.reload_if_changed().unwrap());
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn connections_per_address_are_limited() {
+    let settings = ServerSettings {
+        max_connections_per_ip: Some(2),
+        ..Default::default()
+    };
+    let bans = BanList::default();
+    let home = ip("10.0.0.1");
+
+    assert!(check_connection(&bans, &settings, 1, Some(home), &[home]).is_ok());
+    assert!(check_connection(&bans, &settings, 1, Some(home), &[home, ip("10.0.0.2")]).is_ok());
+    assert_eq!(
+        check_connection(&bans, &settings, 1, Some(home), &[home, home]),
+        Err("Too many connections from your address.")
+    );
+
+    let unlimited = ServerSettings::default();
+    assert!(check_connection(&bans, &unlimited, 1, Some(home), &[home; 10]).is_ok());
+}
+
+#[test]
+fn banned_clients_are_refused() {
+    let mut bans = BanList::default();
+    bans.ban(ClientTarget::Id(7));
+    bans.ban(ClientTarget::Ip(ip("10.0.0.1")));
+    let settings = ServerSettings::default();
+
+    let refused = Err("You are banned from this server.");
+    assert_eq!(check_connection(&bans, &settings, 7, None, &[]), refused);
+    assert_eq!(
+        check_connection(&bans, &settings, 8, Some(ip("10.0.0.1")), &[]),
+        refused
+    );
+    assert!(check_connection(&bans, &settings, 8, Some(ip("10.0.0.2")), &[]).is_ok());
+}
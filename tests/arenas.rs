@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use bevy::prelude::*;
+use cagario::{
+    arena::{Arena, ArenaSettings, Arenas, DEFAULT_SPAWN_RATE, MAX_ARENAS},
+    bot::{bot_think_system, Bot, SeekFoodBrain},
+    cells::Cell,
+    round::Rounds,
+    settings::ServerSettings,
+    Player, PlayerInput,
+};
+
+fn settings(args: &[&str]) -> ServerSettings {
+    let args = ["server"].iter().chain(args).map(|arg| arg.to_string());
+    ServerSettings::from_args(args)
+}
+
+#[test]
+fn too_many_rooms_are_refused() {
+    let rooms: Vec<ArenaSettings> = (0..=MAX_ARENAS)
+        .map(|index| ArenaSettings::new(&format!("room{}", index)))
+        .collect();
+    assert!(Arenas::new(rooms[..MAX_ARENAS].to_vec()).is_ok());
+    assert!(Arenas::new(rooms).is_err());
+}
+
+#[test]
+fn rooms_can_have_their_own_round_length() {
+    let settings = settings(&["--rooms", "main,duel:120,casual", "--round", "300"]);
+    let arenas = Arenas::from_settings(&settings).unwrap();
+    let lengths: Vec<(&str, Option<f32>)> = arenas
+        .iter()
+        .map(|(_, room)| (room.code.as_str(), room.round_duration))
+        .collect();
+    assert_eq!(
+        lengths,
+        [
+            ("main", Some(300.0)),
+            ("duel", Some(120.0)),
+            ("casual", Some(300.0))
+        ]
+    );
+
+    // only the room with a length plays rounds without --round
+    let settings = settings(&["--rooms", "main,duel:120"]);
+    let rounds = Rounds::from_arenas(&Arenas::from_settings(&settings).unwrap());
+    assert!(rounds.get(Arena(0)).is_none());
+    assert_eq!(
+        rounds.get(Arena(1)).map(|round| round.duration),
+        Some(120.0)
+    );
+}
+
+#[test]
+fn food_spawns_at_each_arenas_rate() {
+    let mut arenas =
+        Arenas::new(vec![ArenaSettings::new("main"), ArenaSettings::new("duel")]).unwrap();
+    assert_eq!(arenas.settings(Arena(0)).spawn_rate, DEFAULT_SPAWN_RATE);
+    arenas.set_spawn_rate(Arena(0), 1.0);
+    arenas.set_spawn_rate(Arena(1), 2.0);
+    let step = Duration::from_secs(1);
+
+    assert_eq!(arenas.tick_food_timers(step), [Arena(0)]);
+    assert_eq!(arenas.tick_food_timers(step), [Arena(0), Arena(1)]);
+    assert_eq!(arenas.settings(Arena(1)).spawn_rate, 2.0);
+}
+
+#[test]
+fn bots_only_see_their_own_arena() {
+    let mut app = App::new();
+    app.add_system(bot_think_system);
+    let bot = app
+        .world
+        .spawn((
+            Player {
+                id: 1,
+                name: "Bot 1".to_string(),
+            },
+            Transform::default(),
+            Cell { size: 1.0 },
+            Arena(0),
+            Bot {
+                brain: Box::new(SeekFoodBrain),
+            },
+            PlayerInput::default(),
+        ))
+        .id();
+    let moving = |app: &App| {
+        let input = app.world.get::<PlayerInput>(bot).unwrap();
+        input.up || input.down || input.left || input.right
+    };
+    let big_player = |arena: Arena| {
+        (
+            Player {
+                id: 2,
+                name: "big".to_string(),
+            },
+            Transform::from_xyz(0.0, 0.0, 2.0),
+            Cell { size: 5.0 },
+            arena,
+        )
+    };
+
+    // right next to the bot, but in another room
+    let elsewhere = app.world.spawn(big_player(Arena(1))).id();
+    app.update();
+    assert!(!moving(&app));
+
+    app.world.despawn(elsewhere);
+    app.world.spawn(big_player(Arena(0)));
+    app.update();
+    assert!(moving(&app));
+}
+
+#[test]
+fn ignored_arguments_are_kept_for_the_log() {
+    let settings = settings(&[
+        "--rooms",
+        "main,duel:soon",
+        "--round",
+        "inf",
+        "--frobnicate",
+    ]);
+    assert_eq!(
+        settings.warnings,
+        [
+            "Ignoring --rooms soon, it takes a number of seconds above 0.",
+            "Ignoring --round inf, it takes a number of seconds above 0.",
+            "Ignoring unknown argument --frobnicate.",
+        ]
+    );
+    // the room itself is still hosted, only without rounds
+    assert_eq!(settings.rooms.len(), 2);
+}
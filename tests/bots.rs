@@ -0,0 +1,69 @@
+use bevy::prelude::*;
+use cagario::{
+    arena::Arena,
+    bot::{bot_think_system, Bot, SeekFoodBrain},
+    cells::Cell,
+    team::Team,
+    Player, PlayerInput,
+};
+
+fn think_app() -> App {
+    let mut app = App::new();
+    app.add_system(bot_think_system);
+    app
+}
+
+fn spawn_bot(app: &mut App, team: Option<Team>) -> Entity {
+    let mut bot = app.world.spawn((
+        Player {
+            id: 1,
+            name: "Bot 1".to_string(),
+        },
+        Transform::default(),
+        Cell { size: 1.0 },
+        Arena(0),
+        Bot {
+            brain: Box::new(SeekFoodBrain),
+        },
+        PlayerInput::default(),
+    ));
+    if let Some(team) = team {
+        bot.insert(team);
+    }
+    bot.id()
+}
+
+fn spawn_player(app: &mut App, id: u64, x: f32, z: f32, size: f32, team: Team) {
+    app.world.spawn((
+        Player {
+            id,
+            name: format!("player {}", id),
+        },
+        Transform::from_xyz(x, 0.0, z),
+        Cell { size },
+        Arena(0),
+        team,
+    ));
+}
+
+// (up, down, left, right)
+fn input(app: &App, bot: Entity) -> (bool, bool, bool, bool) {
+    let input = app.world.get::<PlayerInput>(bot).unwrap();
+    (input.up, input.down, input.left, input.right)
+}
+
+#[test]
+fn bots_leave_teammates_alone() {
+    let mut app = think_app();
+    let bot = spawn_bot(&mut app, Some(Team(0)));
+    spawn_player(&mut app, 2, 2.0, 0.0, 5.0, Team(0));
+    spawn_player(&mut app, 3, -2.0, 0.0, 0.5, Team(0));
+
+    app.update();
+    assert_eq!(input(&app, bot), (false, false, false, false));
+
+    // a bigger cell on another team is still a threat
+    spawn_player(&mut app, 4, 0.0, 2.0, 5.0, Team(1));
+    app.update();
+    assert_eq!(input(&app, bot), (true, false, false, false));
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use cagario::{
+    chat::{
+        sanitize_chat, Blocklist, ChatFilter, ChatLog, ChatRate, ServerChat, CHAT_BURST,
+        CHAT_REFILL, MAX_CHAT_LENGTH,
+    },
+    ClientChannel,
+};
+use common::Harness;
+
+const TOO_FAST: &str = "You are sending messages too fast.";
+
+fn server_chat() -> ServerChat {
+    ServerChat::new(Box::new(Blocklist::default()), None)
+}
+
+#[test]
+fn burst_is_allowed_then_throttled() {
+    let mut chat = server_chat();
+    for _ in 0..CHAT_BURST as usize {
+        assert_eq!(chat.allow(1), ChatRate::Allowed);
+    }
+    assert_eq!(chat.allow(1), ChatRate::Throttled { notify: true });
+    assert_eq!(chat.allow(1), ChatRate::Throttled { notify: false });
+    assert_eq!(chat.allow(1), ChatRate::Throttled { notify: false });
+
+    // other clients have their own allowance
+    assert_eq!(chat.allow(2), ChatRate::Allowed);
+}
+
+#[test]
+fn refill_earns_back_a_line_and_a_new_notice() {
+    let mut chat = server_chat();
+    for _ in 0..CHAT_BURST as usize {
+        chat.allow(1);
+    }
+    assert_eq!(chat.allow(1), ChatRate::Throttled { notify: true });
+
+    chat.refill(CHAT_REFILL / 2.0);
+    assert_eq!(chat.allow(1), ChatRate::Throttled { notify: false });
+
+    chat.refill(CHAT_REFILL / 2.0);
+    assert_eq!(chat.allow(1), ChatRate::Allowed);
+    assert_eq!(chat.allow(1), ChatRate::Throttled { notify: true });
+
+    // never more than the burst, however long the client stays quiet
+    chat.refill(CHAT_REFILL * 100.0);
+    for _ in 0..CHAT_BURST as usize {
+        assert_eq!(chat.allow(1), ChatRate::Allowed);
+    }
+    assert_eq!(chat.allow(1), ChatRate::Throttled { notify: true });
+}
+
+#[test]
+fn blocklist_masks_whole_words_ignoring_case() {
+    let blocklist = Blocklist::new(["Darn".to_string(), "  heck ".to_string(), String::new()]);
+    assert_eq!(
+        blocklist.filter(1, "darn it, HECK! darned"),
+        Some("**** it, ***** darned".to_string())
+    );
+    assert_eq!(
+        blocklist.filter(1, "nothing to see"),
+        Some("nothing to see".to_string())
+    );
+}
+
+#[test]
+fn sanitize_strips_control_characters_and_caps_length() {
+    assert_eq!(sanitize_chat("  hi\u{7}\nthere\t "), "hithere");
+    assert_eq!(sanitize_chat(" \n "), "");
+
+    let long = "é".repeat(MAX_CHAT_LENGTH + 10);
+    assert_eq!(sanitize_chat(&long).chars().count(), MAX_CHAT_LENGTH);
+}
+
+#[test]
+fn flooding_gets_one_notice_and_is_scored() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("mallory");
+    harness.connect(client);
+    let client_id = harness.client_id(client);
+
+    for i in 0..20 {
+        let message = bincode::serialize(&format!("spam {}", i)).unwrap();
+        harness.send_raw(client, ClientChannel::Chat, message);
+    }
+
+    let relayed = harness.step_until(60, |harness| {
+        let log = harness.clients[client].world.resource::<ChatLog>();
+        log.lines.iter().filter(|line| !line.notice).count() == CHAT_BURST as usize
+            && log.lines.iter().any(|line| line.text == TOO_FAST)
+    });
+    assert!(relayed, "the burst or the notice never arrived");
+    assert!(harness.violation_score(client_id) > 0.0);
+
+    for _ in 0..30 {
+        harness.step();
+    }
+    let log = harness.clients[client].world.resource::<ChatLog>();
+    let notices = log
+        .lines
+        .iter()
+        .filter(|line| line.text == TOO_FAST)
+        .count();
+    assert_eq!(notices, 1);
+}
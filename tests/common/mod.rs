@@ -0,0 +1,280 @@
+//! Runs a server and any number of clients in one process over loopback UDP.
+//!
+//! Every app gets a manually driven `Time`, so each `step` advances exactly one
+//! frame of `FRAME` on all of them and tests stay independent of the wall clock.
+
+// each test binary only uses part of the harness
+#![allow(dead_code)]
+
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, SystemTime},
+};
+
+use bevy::{
+    asset::AssetPlugin, core::CorePlugin, hierarchy::HierarchyPlugin, prelude::*,
+    scene::ScenePlugin, transform::TransformPlugin,
+};
+use bevy_rapier3d::prelude::{NoUserData, RapierPhysicsPlugin};
+use bevy_renet::{
+    renet::{RenetClient, RenetServer, ServerAuthentication, ServerConfig},
+    run_if_client_connected, RenetClientPlugin, RenetServerPlugin,
+};
+use cagario::{
+    access::{disconnect_refused, BanList, Refusals},
+    arena::Arenas,
+    cells::spawn_spheres,
+    chat::{server_chat_system, Blocklist, ChatLog, ServerChat},
+    client::{
+        client_send_input, client_send_player_commands, client_sync_players, ClientLobby,
+        NetworkMapping,
+    },
+    game_over::GameOverInfo,
+    kill_feed::KillFeed,
+    leaderboard::{ClientLeaderboard, LEADERBOARD_INTERVAL},
+    physics::PhysicsPlugin,
+    profile::ClientProfile,
+    replay::{ReplayPlayback, ReplayPlaybackPlugin},
+    rng::ServerRng,
+    round::ClientRound,
+    server::{move_players_system, server_network_sync, server_update_system},
+    server_connection_config,
+    session::{client_reconnect, expire_sessions, ClientSession, SessionExpired, Sessions},
+    settings::ServerSettings,
+    spawn::SpawnPlacement,
+    validation::{enforce_violation_scores, AntiCheat},
+    ClientChannel, ConnectionData, ControlledPlayer, Game, GameState, PlayerCommand, PlayerInput,
+    ServerLobby, PROTOCOL_ID,
+};
+
+pub const FRAME: Duration = Duration::from_micros(16_667);
+pub const TEST_SEED: u64 = 7;
+
+pub struct Harness {
+    pub server: App,
+    pub server_addr: SocketAddr,
+    pub clients: Vec<App>,
+}
+
+impl Harness {
+    pub fn new() -> Self {
+        Self::with_seed(TEST_SEED)
+    }
+
+    pub fn with_seed(seed: u64) -> Self {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let server_addr = socket.local_addr().unwrap();
+        let server_config =
+            ServerConfig::new(64, PROTOCOL_ID, server_addr, ServerAuthentication::Unsecure);
+        let server = RenetServer::new(
+            current_time(),
+            server_config,
+            server_connection_config(),
+            socket,
+        )
+        .unwrap();
+
+        let mut app = headless_app();
+        app.add_plugin(RenetServerPlugin::default())
+            .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
+            .add_plugin(PhysicsPlugin)
+            .insert_resource(server)
+            .insert_resource(ServerLobby::default())
+            .init_resource::<Arenas>()
+            .init_resource::<BanList>()
+            .init_resource::<Refusals>()
+            .init_resource::<AntiCheat>()
+            .init_resource::<Sessions>()
+            .add_event::<SessionExpired>()
+            .insert_resource(ServerSettings {
+                seed: Some(seed),
+                ..Default::default()
+            })
+            .insert_resource(ServerRng::new(seed))
+            .init_resource::<SpawnPlacement>()
+            .insert_resource(Game {
+                leaderboard_timer: Timer::from_seconds(LEADERBOARD_INTERVAL, TimerMode::Repeating),
+            })
+            .add_system(server_update_system)
+            .add_system(server_network_sync)
+            .add_system(move_players_system)
+            .insert_resource(ServerChat::new(Box::new(Blocklist::default()), None))
+            .add_system(server_chat_system)
+            .add_system(enforce_violation_scores.after(server_update_system))
+            .add_system(expire_sessions.after(server_update_system))
+            .add_system(disconnect_refused);
+
+        Self {
+            server: app,
+            server_addr,
+            clients: Vec::new(),
+        }
+    }
+
+    /// Turns on the server's food spawning, off by default so cells stay where tests put them.
+    pub fn spawn_food(&mut self) {
+        self.server
+            .add_system(spawn_spheres.after(server_update_system));
+    }
+
+    /// Lets the client connect again on its own after its link drops, as the real client does.
+    pub fn enable_reconnect(&mut self, index: usize) {
+        self.clients[index].add_system(client_reconnect);
+    }
+
+    /// Connects a new client and returns its index in `clients`.
+    pub fn add_client(&mut self, name: &str) -> usize {
+        let current_time = current_time();
+        // clients created in the same millisecond still need distinct ids
+        let client_id = current_time.as_millis() as u64 + self.clients.len() as u64;
+        let connection_data = ConnectionData {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let session = ClientSession::new(self.server_addr, client_id, connection_data);
+        let client = session.renet_client();
+
+        let mut app = client_app();
+        app.add_plugin(RenetClientPlugin::default())
+            .insert_resource(client)
+            .insert_resource(session)
+            .add_system(client_send_input.with_run_criteria(run_if_client_connected))
+            .add_system(client_send_player_commands.with_run_criteria(run_if_client_connected))
+            .add_system(client_sync_players.with_run_criteria(run_if_client_connected));
+
+        self.clients.push(app);
+        self.clients.len() - 1
+    }
+
+    /// Advances the server, then every client, by one frame.
+    pub fn step(&mut self) {
+        advance_time(&mut self.server);
+        self.server.update();
+        for client in self.clients.iter_mut() {
+            advance_time(client);
+            client.update();
+        }
+    }
+
+    /// Steps until `condition` holds, giving up after `max_frames`.
+    pub fn step_until(
+        &mut self,
+        max_frames: usize,
+        condition: impl Fn(&mut Harness) -> bool,
+    ) -> bool {
+        for _ in 0..max_frames {
+            self.step();
+            if condition(self) {
+                return true;
+            }
+        }
+        false
+    }
+
+    pub fn client_id(&self, index: usize) -> u64 {
+        self.clients[index]
+            .world
+            .resource::<RenetClient>()
+            .client_id()
+    }
+
+    /// Queues a payload on the client as is, skipping its own serialization.
+    pub fn send_raw(&mut self, index: usize, channel: ClientChannel, payload: Vec<u8>) {
+        self.clients[index]
+            .world
+            .resource_mut::<RenetClient>()
+            .send_message(channel, payload);
+    }
+
+    pub fn violation_score(&self, client_id: u64) -> f32 {
+        self.server.world.resource::<AntiCheat>().score(client_id)
+    }
+
+    pub fn server_player(&self, client_id: u64) -> Option<Entity> {
+        self.server
+            .world
+            .resource::<ServerLobby>()
+            .players
+            .get(&client_id)
+            .copied()
+    }
+
+    pub fn controlled_player(&mut self, index: usize) -> Option<Entity> {
+        self.clients[index]
+            .world
+            .query_filtered::<Entity, With<ControlledPlayer>>()
+            .iter(&self.clients[index].world)
+            .next()
+    }
+
+    /// Steps until the client is connected and controls its own cell.
+    pub fn connect(&mut self, index: usize) {
+        let client_id = self.client_id(index);
+        let connected = self.step_until(600, |harness| {
+            harness.server_player(client_id).is_some() && harness.controlled_player(index).is_some()
+        });
+        assert!(connected, "client {} never got its cell", index);
+    }
+}
+
+impl Default for Harness {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A client that plays `playback` back instead of connecting, as `client --replay` does.
+pub fn replay_viewer(playback: ReplayPlayback) -> App {
+    let mut app = client_app();
+    app.insert_resource(playback)
+        .add_plugin(ReplayPlaybackPlugin);
+    app
+}
+
+/// Advances one app on its own by one frame.
+pub fn step_app(app: &mut App) {
+    advance_time(app);
+    app.update();
+}
+
+// what client_sync_players needs, whether it reads from a server or a replay
+fn client_app() -> App {
+    let mut app = headless_app();
+    app.add_state(GameState::InGame)
+        .add_event::<PlayerCommand>()
+        .insert_resource(PlayerInput::default())
+        .insert_resource(ClientLobby::default())
+        .insert_resource(NetworkMapping::default())
+        .init_resource::<GameOverInfo>()
+        .init_resource::<ClientLeaderboard>()
+        .init_resource::<ChatLog>()
+        .init_resource::<ClientRound>()
+        .init_resource::<ClientProfile>()
+        .init_resource::<KillFeed>();
+    app
+}
+
+fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugin(CorePlugin::default())
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(AssetPlugin::default())
+        .add_plugin(ScenePlugin)
+        .add_asset::<Mesh>()
+        .add_asset::<StandardMaterial>()
+        .insert_resource(Time::default());
+    app
+}
+
+fn advance_time(app: &mut App) {
+    let mut time = app.world.resource_mut::<Time>();
+    let last_update = time.last_update().unwrap_or_else(|| time.startup());
+    time.update_with_instant(last_update + FRAME);
+}
+
+fn current_time() -> Duration {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use bevy::prelude::*;
+use cagario::{
+    chat::ChatLog,
+    validation::{decode, validate_command, Violation, MAX_CAST_RANGE},
+    ClientChannel, PlayerCommand, PlayerInput,
+};
+use common::Harness;
+
+fn client_was_told(harness: &Harness, index: usize, text: &str) -> bool {
+    harness.clients[index]
+        .world
+        .resource::<ChatLog>()
+        .lines
+        .iter()
+        .any(|line| line.notice && line.text.contains(text))
+}
+
+#[test]
+fn garbage_payloads_are_scored_not_fatal() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("mallory");
+    harness.connect(client);
+    let client_id = harness.client_id(client);
+
+    // not a PlayerCommand at all
+    harness.send_raw(client, ClientChannel::Command, vec![0xff; 7]);
+    // a bool that is neither 0 nor 1
+    harness.send_raw(client, ClientChannel::Input, vec![2, 0, 0, 0]);
+
+    let scored = harness.step_until(60, |harness| harness.violation_score(client_id) > 7.0);
+    assert!(scored, "malformed messages were not reported");
+    assert!(
+        harness.server_player(client_id).is_some(),
+        "two bad messages should not be enough for a kick"
+    );
+}
+
+#[test]
+fn non_finite_aim_is_rejected() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("mallory");
+    harness.connect(client);
+    let client_id = harness.client_id(client);
+
+    for cast_at in [Vec3::NAN, Vec3::new(f32::INFINITY, 0.0, 0.0)] {
+        let command = bincode::serialize(&PlayerCommand::BasicAttack { cast_at }).unwrap();
+        harness.send_raw(client, ClientChannel::Command, command);
+    }
+
+    let scored = harness.step_until(60, |harness| harness.violation_score(client_id) > 7.0);
+    assert!(scored, "non-finite aim was not reported");
+    assert!(harness.server_player(client_id).is_some());
+}
+
+#[test]
+fn aim_is_clamped_to_the_cast_range() {
+    let origin = Vec3::new(10.0, 0.0, 10.0);
+    let command = PlayerCommand::BasicAttack {
+        cast_at: Vec3::new(1.0e9, 0.0, 10.0),
+    };
+    match validate_command(command, Some(origin)) {
+        Ok(PlayerCommand::BasicAttack { cast_at }) => {
+            assert!((cast_at.distance(origin) - MAX_CAST_RANGE).abs() < 1.0e-3);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn trailing_and_truncated_payloads_do_not_decode() {
+    let mut input = bincode::serialize(&PlayerInput::default()).unwrap();
+    input.push(0);
+    assert!(matches!(
+        decode::<PlayerInput>(&input),
+        Err(Violation::Malformed)
+    ));
+    assert!(matches!(
+        decode::<PlayerInput>(&[0, 1]),
+        Err(Violation::Malformed)
+    ));
+
+    // a chat line claiming to be far longer than any client would send
+    let mut chat = u64::MAX.to_le_bytes().to_vec();
+    chat.extend_from_slice(b"hello");
+    assert!(matches!(decode::<String>(&chat), Err(Violation::Malformed)));
+}
+
+#[test]
+fn repeated_violations_get_the_client_kicked() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("mallory");
+    harness.connect(client);
+    let client_id = harness.client_id(client);
+
+    for _ in 0..4 {
+        harness.send_raw(client, ClientChannel::Command, vec![0xff; 7]);
+    }
+
+    let kicked = harness.step_until(300, |harness| harness.server_player(client_id).is_none());
+    assert!(kicked, "client was never kicked");
+    assert!(client_was_told(&harness, client, "Kicked"));
+}
+
+#[test]
+fn command_floods_are_rate_limited() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("mallory");
+    harness.connect(client);
+    let client_id = harness.client_id(client);
+
+    let respawn = bincode::serialize(&PlayerCommand::Respawn).unwrap();
+    for _ in 0..200 {
+        harness.send_raw(client, ClientChannel::Command, respawn.clone());
+    }
+
+    let kicked = harness.step_until(300, |harness| harness.server_player(client_id).is_none());
+    assert!(kicked, "flooding client was never kicked");
+}
+
+#[test]
+fn well_behaved_clients_stay_clean() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("alice");
+    harness.connect(client);
+    let client_id = harness.client_id(client);
+
+    harness.clients[client].world.insert_resource(PlayerInput {
+        up: true,
+        right: true,
+        ..Default::default()
+    });
+    for _ in 0..120 {
+        harness.step();
+    }
+
+    assert_eq!(harness.violation_score(client_id), 0.0);
+    assert!(harness.server_player(client_id).is_some());
+}
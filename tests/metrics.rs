@@ -0,0 +1,106 @@
+use cagario::metrics::{render_metrics, ChannelCounters, MetricsSample};
+
+fn sample() -> MetricsSample {
+    MetricsSample {
+        clients: 3,
+        spectators: 1,
+        players: 2,
+        bots: 4,
+        food: 50,
+        tick_seconds: 0.0025,
+        channels: vec![
+            (
+                0,
+                ChannelCounters {
+                    messages: 120,
+                    bytes: 4800,
+                },
+            ),
+            (
+                1,
+                ChannelCounters {
+                    messages: 7,
+                    bytes: 210,
+                },
+            ),
+        ],
+        network: vec![(11, 35.5, 0.25)],
+        players_eaten: 5,
+        food_eaten: 40,
+        players_eaten_per_minute: 1,
+        food_eaten_per_minute: 12,
+    }
+}
+
+#[test]
+fn page_has_every_sample() {
+    let page = render_metrics(&sample());
+    let lines: Vec<&str> = page.lines().filter(|l| !l.starts_with('#')).collect();
+    assert_eq!(
+        lines,
+        vec![
+            "cagario_clients 3",
+            "cagario_spectators 1",
+            "cagario_cells{kind=\"player\"} 2",
+            "cagario_cells{kind=\"bot\"} 4",
+            "cagario_cells{kind=\"food\"} 50",
+            "cagario_tick_seconds 0.002500",
+            "cagario_messages_sent_total{channel=\"NetworkedEntities\"} 120",
+            "cagario_messages_sent_total{channel=\"ServerMessages\"} 7",
+            "cagario_bytes_sent_total{channel=\"NetworkedEntities\"} 4800",
+            "cagario_bytes_sent_total{channel=\"ServerMessages\"} 210",
+            "cagario_client_rtt_milliseconds{client=\"11\"} 35.5",
+            "cagario_client_packet_loss{client=\"11\"} 0.25",
+            "cagario_eats_total{kind=\"player\"} 5",
+            "cagario_eats_total{kind=\"food\"} 40",
+            "cagario_eats_per_minute{kind=\"player\"} 1",
+            "cagario_eats_per_minute{kind=\"food\"} 12",
+        ]
+    );
+}
+
+#[test]
+fn every_metric_has_help_and_type_before_its_samples() {
+    let page = render_metrics(&sample());
+    let mut declared: Vec<(String, String)> = Vec::new();
+    let mut helped: Vec<String> = Vec::new();
+    for line in page.lines() {
+        if let Some(rest) = line.strip_prefix("# HELP ") {
+            let (name, help) = rest.split_once(' ').unwrap();
+            assert!(!help.is_empty(), "{} has an empty help", name);
+            helped.push(name.to_string());
+        } else if let Some(rest) = line.strip_prefix("# TYPE ") {
+            let (name, kind) = rest.split_once(' ').unwrap();
+            assert!(helped.iter().any(|h| h == name), "{} has no help", name);
+            assert!(
+                kind == "gauge" || kind == "counter",
+                "{} is a {}",
+                name,
+                kind
+            );
+            declared.push((name.to_string(), kind.to_string()));
+        } else {
+            let name = line.split(['{', ' ']).next().unwrap();
+            let (_, kind) = declared
+                .last()
+                .filter(|(declared, _)| declared == name)
+                .unwrap_or_else(|| panic!("{} is not under its own TYPE line", name));
+            if kind == "counter" {
+                assert!(name.ends_with("_total"), "counter {} lacks _total", name);
+            }
+            let value = line.rsplit(' ').next().unwrap();
+            assert!(value.parse::<f64>().is_ok(), "{} is not a number", line);
+        }
+    }
+}
+
+#[test]
+fn empty_server_still_declares_every_metric() {
+    let page = render_metrics(&MetricsSample::default());
+    assert!(page.contains("cagario_clients 0\n"));
+    assert!(page.contains("cagario_eats_per_minute{kind=\"food\"} 0\n"));
+    // no channel or client yet, the families are declared without samples
+    assert!(page.contains("# TYPE cagario_messages_sent_total counter\n"));
+    assert!(page.contains("# TYPE cagario_client_rtt_milliseconds gauge\n"));
+    assert!(!page.contains("cagario_client_rtt_milliseconds{"));
+}
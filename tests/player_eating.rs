@@ -0,0 +1,100 @@
+mod common;
+
+use bevy::prelude::*;
+use cagario::{cells::Cell, client::ClientLobby, physics::grown_size, player::INITIAL_PLAYER_SIZE};
+use common::Harness;
+
+fn server_size(harness: &Harness, entity: Entity) -> f32 {
+    harness.server.world.get::<Cell>(entity).unwrap().size
+}
+
+/// Connects two clients and puts the second cell right on top of the first one.
+fn overlapping_players(eater_size: f32, victim_size: f32) -> (Harness, [(usize, u64, Entity); 2]) {
+    let mut harness = Harness::new();
+    let eater = harness.add_client("alice");
+    let victim = harness.add_client("bob");
+    harness.connect(eater);
+    harness.connect(victim);
+
+    let players = [eater, victim].map(|index| {
+        let client_id = harness.client_id(index);
+        (index, client_id, harness.server_player(client_id).unwrap())
+    });
+    let [(_, _, eater_entity), (_, _, victim_entity)] = players;
+
+    let world = &mut harness.server.world;
+    world.get_mut::<Cell>(eater_entity).unwrap().size = eater_size;
+    world.get_mut::<Cell>(victim_entity).unwrap().size = victim_size;
+    let translation = world.get::<Transform>(eater_entity).unwrap().translation;
+    world
+        .get_mut::<Transform>(victim_entity)
+        .unwrap()
+        .translation = translation;
+
+    (harness, players)
+}
+
+#[test]
+fn eater_grows_on_the_server() {
+    let eater_size = 2.0;
+    let (mut harness, [(eater, _, eater_entity), (_, victim_id, _)]) =
+        overlapping_players(eater_size, INITIAL_PLAYER_SIZE);
+
+    let eaten = harness.step_until(60, |harness| harness.server_player(victim_id).is_none());
+    assert!(eaten, "victim was never eaten");
+
+    let expected = grown_size(eater_size, INITIAL_PLAYER_SIZE);
+    assert_eq!(server_size(&harness, eater_entity), expected);
+
+    // the client is told the server's size, not one it works out on its own
+    let synced = harness.step_until(60, |harness| {
+        let controlled = harness.controlled_player(eater).unwrap();
+        harness.clients[eater]
+            .world
+            .get::<Cell>(controlled)
+            .unwrap()
+            .size
+            == expected
+    });
+    assert!(synced, "eater's client never saw the server's size");
+}
+
+#[test]
+fn eaten_player_is_removed_everywhere() {
+    let (mut harness, [(eater, eater_id, eater_entity), (victim, victim_id, victim_entity)]) =
+        overlapping_players(2.0, INITIAL_PLAYER_SIZE);
+
+    let removed = harness.step_until(120, |harness| {
+        harness.server_player(victim_id).is_none()
+            && harness.controlled_player(victim).is_none()
+            && !harness.clients[eater]
+                .world
+                .resource::<ClientLobby>()
+                .players
+                .contains_key(&victim_id)
+    });
+    assert!(
+        removed,
+        "victim was not removed on the server and both clients"
+    );
+
+    assert!(harness.server.world.get_entity(victim_entity).is_none());
+    assert_eq!(harness.server_player(eater_id), Some(eater_entity));
+    assert!(harness.controlled_player(eater).is_some());
+}
+
+#[test]
+fn equal_sizes_do_not_eat_each_other() {
+    let size = 1.5;
+    let (mut harness, [(_, first_id, first_entity), (_, second_id, second_entity)]) =
+        overlapping_players(size, size);
+
+    for _ in 0..60 {
+        harness.step();
+    }
+
+    assert_eq!(harness.server_player(first_id), Some(first_entity));
+    assert_eq!(harness.server_player(second_id), Some(second_entity));
+    assert_eq!(server_size(&harness, first_entity), size);
+    assert_eq!(server_size(&harness, second_entity), size);
+}
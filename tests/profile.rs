@@ -0,0 +1,166 @@
+use std::{fs, path::PathBuf};
+
+use cagario::profile::{ProfileStats, ProfileStore};
+
+fn temp_file(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("cagario-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+}
+
+#[test]
+fn saved_profiles_load_back() {
+    let path = temp_file("profiles-round-trip.tsv");
+    let mut store = ProfileStore::load(&path).unwrap();
+    assert!(store.is_empty());
+
+    store.connect(1, 77, "Alice");
+    store.begin_life(1, 0.0, Some("Red"));
+    store.record_mass(1, 12.5);
+    store.record_kill(1);
+    assert!(store.end_life(1, 30.0, Some(20.25)));
+    store.begin_life(1, 30.0, Some("Blue"));
+    assert!(store.end_life(1, 40.0, None));
+    store.connect(2, 88, "tab\there\nnewline");
+    store.begin_life(2, 0.0, None);
+    assert!(store.end_life(2, 5.5, Some(3.0)));
+    // written on the writer thread, closing waits for it
+    store.close();
+
+    let loaded = ProfileStore::load(&path).unwrap();
+    assert_eq!(loaded.len(), 2);
+    for key in [77, 88] {
+        let saved = store.profile(key).unwrap();
+        let read = loaded.profile(key).unwrap();
+        assert_eq!(read.stats(), saved.stats());
+        assert_eq!(read.color_time, saved.color_time);
+    }
+    assert_eq!(loaded.profile(77).unwrap().name, "Alice");
+    // control characters would break the line and column layout
+    assert_eq!(loaded.profile(88).unwrap().name, "tab here newline");
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn unreadable_lines_are_skipped() {
+    let path = temp_file("profiles-hand-edited.tsv");
+    fs::write(
+        &path,
+        "# key\tgames\tbest mass\tkills\tseconds alive\tseconds per colour\tname\n\
+         5\t3\t40.50\t2\t90.0\tBlue=60.0,Red=30.0\tBob\n\
+         6\tthree\t1\t0\t1\t\tBroken\n\
+         7\t1\t2.00\t0\t10.0\t\t\n\
+         \n",
+    )
+    .unwrap();
+
+    let store = ProfileStore::load(&path).unwrap();
+    assert_eq!(store.len(), 2);
+    let bob = store.profile(5).unwrap();
+    assert_eq!(bob.name, "Bob");
+    assert_eq!(
+        bob.stats(),
+        ProfileStats {
+            games_played: 3,
+            best_mass: 40.5,
+            kills: 2,
+            time_alive: 90.0,
+            favorite_color: Some("Blue".to_string()),
+        }
+    );
+    assert!(store.profile(6).is_none());
+    // no colour and no name is still a profile
+    assert_eq!(store.profile(7).unwrap().favorite_color(), None);
+    let _ = fs::remove_file(&path);
+}
+
+#[test]
+fn a_life_is_folded_into_the_profile_when_it_ends() {
+    let mut store = ProfileStore::default();
+    store.connect(1, 77, "Alice");
+
+    // mass and kills only count during a life
+    store.record_mass(1, 100.0);
+    store.record_kill(1);
+    assert!(!store.end_life(1, 10.0, None));
+
+    store.begin_life(1, 10.0, Some("Green"));
+    store.record_mass(1, 8.0);
+    store.record_mass(1, 6.0);
+    store.record_kill(1);
+    store.record_kill(1);
+    assert!(store.end_life(1, 25.0, Some(7.0)));
+    // the life is gone once it ended
+    assert!(!store.end_life(1, 30.0, None));
+
+    assert_eq!(
+        store.stats(1),
+        Some(ProfileStats {
+            games_played: 1,
+            best_mass: 8.0,
+            kills: 2,
+            time_alive: 15.0,
+            favorite_color: Some("Green".to_string()),
+        })
+    );
+}
+
+#[test]
+fn respawning_or_leaving_ends_the_current_life() {
+    let mut store = ProfileStore::default();
+    store.connect(1, 77, "Alice");
+
+    store.begin_life(1, 0.0, Some("Red"));
+    store.begin_life(1, 10.0, Some("Blue"));
+    store.record_mass(1, 30.0);
+    assert!(store.disconnect(1, 40.0));
+    assert!(!store.disconnect(1, 50.0));
+
+    let profile = store.profile(77).unwrap();
+    assert_eq!(profile.games_played, 2);
+    assert_eq!(profile.best_mass, 30.0);
+    assert_eq!(profile.time_alive, 40.0);
+    assert_eq!(profile.favorite_color(), Some("Blue"));
+    // the client is forgotten, only the profile stays
+    assert_eq!(store.stats(1), None);
+}
+
+#[test]
+fn free_for_all_lives_have_no_favourite_colour() {
+    let mut store = ProfileStore::default();
+    store.connect(1, 77, "Alice");
+    store.begin_life(1, 0.0, None);
+    assert!(store.end_life(1, 20.0, None));
+
+    let stats = store.stats(1).unwrap();
+    assert_eq!(stats.games_played, 1);
+    assert_eq!(stats.time_alive, 20.0);
+    assert_eq!(stats.favorite_color, None);
+}
+
+#[test]
+fn anonymous_clients_are_not_tracked() {
+    let mut store = ProfileStore::default();
+    store.connect(1, 0, "Nobody");
+    store.begin_life(1, 0.0, Some("Red"));
+    assert!(!store.end_life(1, 10.0, None));
+    assert!(store.is_empty());
+    assert_eq!(store.stats(1), None);
+}
+
+#[test]
+fn profiles_are_only_written_when_saved() {
+    let path = temp_file("profiles-deferred.tsv");
+    let mut store = ProfileStore::load(&path).unwrap();
+    store.connect(1, 77, "Alice");
+    store.begin_life(1, 0.0, None);
+    assert!(store.end_life(1, 10.0, None));
+    // the game ended, but the file waits for the next save
+    assert!(!path.exists());
+
+    store.save();
+    store.close();
+    let loaded = ProfileStore::load(&path).unwrap();
+    assert_eq!(loaded.profile(77).unwrap().games_played, 1);
+    let _ = fs::remove_file(&path);
+}
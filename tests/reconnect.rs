@@ -0,0 +1,191 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_renet::renet::RenetClient;
+use cagario::{
+    cells::Cell,
+    client::ClientLobby,
+    session::{ClientSession, Sessions, DEFAULT_RECONNECT_GRACE},
+    settings::ServerSettings,
+    ClientChannel, PlayerCommand,
+};
+use common::Harness;
+
+fn set_grace(harness: &mut Harness, grace: f32) {
+    harness
+        .server
+        .world
+        .resource_mut::<ServerSettings>()
+        .reconnect_grace = Some(grace);
+}
+
+fn drop_link(harness: &mut Harness, index: usize) {
+    harness.clients[index]
+        .world
+        .resource_mut::<RenetClient>()
+        .disconnect();
+}
+
+fn is_suspended(harness: &Harness, client_id: u64) -> bool {
+    harness
+        .server
+        .world
+        .resource::<Sessions>()
+        .is_suspended(client_id)
+}
+
+#[test]
+fn dropped_client_gets_its_cell_back() {
+    let mut harness = Harness::new();
+    set_grace(&mut harness, 30.0);
+    let client = harness.add_client("alice");
+    harness.connect(client);
+    harness.enable_reconnect(client);
+
+    let client_id = harness.client_id(client);
+    let player_entity = harness.server_player(client_id).unwrap();
+    harness
+        .server
+        .world
+        .get_mut::<Cell>(player_entity)
+        .unwrap()
+        .size = 2.5;
+
+    drop_link(&mut harness, client);
+    let reconnecting = harness.step_until(10, |harness| {
+        harness.clients[client]
+            .world
+            .resource::<ClientSession>()
+            .is_reconnecting()
+    });
+    assert!(reconnecting, "client never noticed the dropped link");
+
+    let resumed = harness.step_until(1200, |harness| {
+        let controlled = match harness.controlled_player(client) {
+            Some(controlled) => controlled,
+            None => return false,
+        };
+        let session = harness.clients[client].world.resource::<ClientSession>();
+        !session.is_reconnecting()
+            && harness.clients[client]
+                .world
+                .get::<Cell>(controlled)
+                .map_or(false, |cell| cell.size == 2.5)
+    });
+    assert!(resumed, "client never got its cell back");
+    assert_eq!(harness.server_player(client_id), Some(player_entity));
+    assert!(!is_suspended(&harness, client_id));
+}
+
+#[test]
+fn cell_is_removed_once_the_grace_period_ends() {
+    let mut harness = Harness::new();
+    set_grace(&mut harness, 1.0);
+    let leaving = harness.add_client("alice");
+    let staying = harness.add_client("bob");
+    harness.connect(leaving);
+    harness.connect(staying);
+
+    let leaving_id = harness.client_id(leaving);
+    drop_link(&mut harness, leaving);
+    let suspended = harness.step_until(60, |harness| is_suspended(harness, leaving_id));
+    assert!(suspended, "dropped client was not given a grace period");
+    assert!(harness.server_player(leaving_id).is_some());
+
+    let removed = harness.step_until(300, |harness| {
+        harness.server_player(leaving_id).is_none()
+            && !harness.clients[staying]
+                .world
+                .resource::<ClientLobby>()
+                .players
+                .contains_key(&leaving_id)
+    });
+    assert!(removed, "cell outlived the grace period");
+    assert!(!is_suspended(&harness, leaving_id));
+}
+
+#[test]
+fn leaving_client_loses_its_cell_right_away() {
+    let mut harness = Harness::new();
+    set_grace(&mut harness, 30.0);
+    let leaving = harness.add_client("alice");
+    let staying = harness.add_client("bob");
+    harness.connect(leaving);
+    harness.connect(staying);
+
+    let leaving_id = harness.client_id(leaving);
+    let leave = bincode::serialize(&PlayerCommand::Leave).unwrap();
+    harness.send_raw(leaving, ClientChannel::Command, leave);
+    let removed = harness.step_until(60, |harness| {
+        harness.server_player(leaving_id).is_none()
+            && !harness.clients[staying]
+                .world
+                .resource::<ClientLobby>()
+                .players
+                .contains_key(&leaving_id)
+    });
+    assert!(removed, "leaving client's cell was kept");
+    assert!(!is_suspended(&harness, leaving_id));
+
+    // the server ends the connection itself
+    let disconnected = harness.step_until(60, |harness| {
+        harness.clients[leaving]
+            .world
+            .resource::<RenetClient>()
+            .disconnected()
+            .is_some()
+    });
+    assert!(disconnected, "leaving client was never disconnected");
+}
+
+#[test]
+fn unreachable_server_is_not_a_reconnect() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("alice");
+    harness.enable_reconnect(client);
+    // refused before the first connection, as when the server isn't there
+    harness.clients[client]
+        .world
+        .resource_mut::<RenetClient>()
+        .disconnect();
+
+    let gave_up = harness.step_until(10, |harness| {
+        harness.clients[client]
+            .world
+            .resource::<ClientSession>()
+            .is_unreachable()
+    });
+    assert!(gave_up, "client never noticed the failed connection");
+    harness.step_until(120, |_| false);
+    let session = harness.clients[client].world.resource::<ClientSession>();
+    assert!(!session.is_reconnecting());
+    assert!(harness.server_player(harness.client_id(client)).is_none());
+}
+
+#[test]
+fn reconnect_grace_must_be_a_finite_number_of_seconds() {
+    let grace = |value: &str| {
+        let args = ["server", "--reconnect-grace", value].map(str::to_string);
+        ServerSettings::from_args(args.into_iter()).reconnect_grace
+    };
+    assert_eq!(grace("60"), Some(60.0));
+    assert_eq!(grace("0"), None);
+    for ignored in ["inf", "NaN", "-5", "soon"] {
+        assert_eq!(grace(ignored), Some(DEFAULT_RECONNECT_GRACE), "{}", ignored);
+    }
+}
+
+#[test]
+fn suspended_session_needs_its_token() {
+    let mut sessions = Sessions::default();
+    let token = sessions.token(7);
+    sessions.suspend(7, 10.0);
+
+    assert!(sessions.resume(7, token.wrapping_add(1)).is_err());
+    assert!(sessions.is_suspended(7));
+    assert_eq!(sessions.resume(7, token), Ok(true));
+    assert_eq!(sessions.token(7), token);
+
+    // nothing to resume for a client that never dropped
+    assert_eq!(sessions.resume(8, token), Ok(false));
+}
@@ -0,0 +1,225 @@
+mod common;
+
+use std::fs;
+
+use bevy::prelude::*;
+use cagario::{
+    arena::Arena,
+    cells::Cell,
+    client::{client_sync_players, NetworkMapping},
+    replay::{
+        read_replay, rewind_replay_world, rotate_replay_per_match, ReplayFrame, ReplayHeader,
+        ReplayPlayback, ReplayRecorder, ReplayTarget, REPLAY_VERSION,
+    },
+    server::server_update_system,
+    Player, ServerChannel, ServerMessages,
+};
+use common::{replay_viewer, step_app, Harness, FRAME};
+
+const PLAYER: u32 = 1;
+const EARLY_FOOD: u32 = 2;
+const LATE_FOOD: u32 = 3;
+
+const DUEL_FOOD: u32 = 4;
+
+fn frame(time: f64, message: ServerMessages) -> ReplayFrame {
+    arena_frame(time, 0, message)
+}
+
+fn arena_frame(time: f64, arena: u32, message: ServerMessages) -> ReplayFrame {
+    ReplayFrame {
+        tick: (time / FRAME.as_secs_f64()) as u64,
+        time,
+        channel: ServerChannel::ServerMessages.into(),
+        target: ReplayTarget::Arena(arena),
+        payload: bincode::serialize(&message).unwrap(),
+    }
+}
+
+fn food(entity: u32) -> ServerMessages {
+    ServerMessages::SpawnNpcCell {
+        entity: Entity::from_raw(entity),
+        translation: [entity as f32, 0.0, 0.0],
+        size: 0.5,
+    }
+}
+
+/// A player and some food at the start, more food after a second, the first food eaten after two.
+///
+/// A second arena only has its own food.
+fn playback() -> ReplayPlayback {
+    let header = ReplayHeader {
+        version: REPLAY_VERSION,
+        seed: 0,
+        started_at: 0,
+        rooms: vec!["main".to_string(), "duel".to_string()],
+    };
+    let frames = vec![
+        frame(
+            0.0,
+            ServerMessages::PlayerCreate {
+                entity: Entity::from_raw(PLAYER),
+                id: 1,
+                translation: [0.0, 0.0, 0.0],
+                team: None,
+            },
+        ),
+        frame(0.0, food(EARLY_FOOD)),
+        frame(1.0, food(LATE_FOOD)),
+        arena_frame(0.5, 1, food(DUEL_FOOD)),
+        frame(
+            2.0,
+            ServerMessages::DespawnEntity {
+                entity: Entity::from_raw(EARLY_FOOD),
+            },
+        ),
+    ];
+    ReplayPlayback::new(header, frames)
+}
+
+/// The server entities the viewer has spawned, and how many cells are in its world.
+fn world_state(app: &mut App) -> (Vec<u32>, usize) {
+    let mut mapped: Vec<u32> = app
+        .world
+        .resource::<NetworkMapping>()
+        .0
+        .keys()
+        .map(|entity| entity.index())
+        .collect();
+    mapped.sort_unstable();
+    let cells = app
+        .world
+        .query_filtered::<Entity, With<Cell>>()
+        .iter(&app.world)
+        .count();
+    (mapped, cells)
+}
+
+fn play_to_end(app: &mut App) {
+    for _ in 0..180 {
+        step_app(app);
+    }
+    assert_eq!(world_state(app), (vec![PLAYER, LATE_FOOD], 2));
+}
+
+#[test]
+fn seeking_backwards_rebuilds_the_world() {
+    let mut app = replay_viewer(playback());
+    play_to_end(&mut app);
+
+    app.world.resource_mut::<ReplayPlayback>().seek(0.5);
+    step_app(&mut app);
+    assert_eq!(world_state(&mut app), (vec![PLAYER, EARLY_FOOD], 2));
+
+    // nothing left over from before the seek shows up later
+    for _ in 0..10 {
+        step_app(&mut app);
+    }
+    assert_eq!(world_state(&mut app), (vec![PLAYER, EARLY_FOOD], 2));
+}
+
+#[derive(Resource)]
+struct SeekTo(Option<f64>);
+
+// the controls used to be able to run here, after the old world was cleared
+fn seek_after_rewind(mut seek_to: ResMut<SeekTo>, mut playback: ResMut<ReplayPlayback>) {
+    if let Some(time) = seek_to.0.take() {
+        playback.seek(time);
+    }
+}
+
+#[test]
+fn seeking_between_rewind_and_sync_rebuilds_the_world() {
+    let mut app = replay_viewer(playback());
+    app.insert_resource(SeekTo(None)).add_system(
+        seek_after_rewind
+            .after(rewind_replay_world)
+            .before(client_sync_players),
+    );
+    play_to_end(&mut app);
+
+    app.world.resource_mut::<SeekTo>().0 = Some(1.5);
+    // too late in this frame, the seek applies on the next one
+    step_app(&mut app);
+    assert_eq!(world_state(&mut app), (vec![PLAYER, LATE_FOOD], 2));
+    for _ in 0..3 {
+        step_app(&mut app);
+        assert_eq!(
+            world_state(&mut app),
+            (vec![PLAYER, EARLY_FOOD, LATE_FOOD], 3)
+        );
+    }
+}
+
+#[test]
+fn another_arena_can_be_watched() {
+    let mut app = replay_viewer(playback());
+    play_to_end(&mut app);
+
+    let mut playback = app.world.resource_mut::<ReplayPlayback>();
+    assert_eq!(playback.arenas(), &[0, 1]);
+    assert_eq!(playback.room(1), "duel");
+    playback.select_arena(1);
+    step_app(&mut app);
+    assert_eq!(world_state(&mut app), (vec![DUEL_FOOD], 1));
+
+    app.world.resource_mut::<ReplayPlayback>().select_arena(0);
+    step_app(&mut app);
+    assert_eq!(world_state(&mut app), (vec![PLAYER, LATE_FOOD], 2));
+}
+
+#[test]
+fn match_snapshot_keeps_the_size_of_grown_cells() {
+    let dir = std::env::temp_dir().join(format!("cagario-{}-snapshot", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+
+    let mut harness = Harness::new();
+    harness
+        .server
+        .insert_resource(ReplayRecorder::new(
+            dir.clone(),
+            1,
+            vec!["main".to_string()],
+        ))
+        .add_system(rotate_replay_per_match.before(server_update_system));
+    // a cell that grew before anyone joined, as bots do
+    let grown = harness
+        .server
+        .world
+        .spawn((
+            Player {
+                id: 99,
+                name: "bot".to_string(),
+            },
+            Cell { size: 3.0 },
+            Transform::default(),
+            Arena(0),
+        ))
+        .id();
+    let client = harness.add_client("alice");
+    harness.connect(client);
+    harness
+        .server
+        .world
+        .resource_mut::<ReplayRecorder>()
+        .close();
+
+    let path = fs::read_dir(&dir).unwrap().next().unwrap().unwrap().path();
+    let (_, frames) = read_replay(&path).unwrap();
+    // what a viewer of the arena gets, not what the joining client was sent
+    let sizes: Vec<f32> = frames
+        .iter()
+        .filter(|frame| {
+            frame.channel == u8::from(ServerChannel::ServerMessages)
+                && frame.target == ReplayTarget::Arena(0)
+        })
+        .filter_map(|frame| bincode::deserialize(&frame.payload).ok())
+        .filter_map(|message| match message {
+            ServerMessages::UpdateEntityCell { entity, size } if entity == grown => Some(size),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(sizes.first(), Some(&3.0));
+    let _ = fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,196 @@
+mod common;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::Collider;
+use cagario::{
+    cells::{Cell, NpcCell},
+    client::{ClientLobby, NetworkMapping},
+    physics::PhysicsBundle,
+    player::INITIAL_PLAYER_SIZE,
+    rng::ServerRng,
+    spawn::SpawnPlacement,
+    ConnectionData, MAX_PLAYER_NAME_LENGTH, MAX_ROOM_CODE_LENGTH,
+};
+use common::Harness;
+
+fn count<F: bevy::ecs::query::ReadOnlyWorldQuery>(app: &mut App) -> usize {
+    app.world.query_filtered::<(), F>().iter(&app.world).count()
+}
+
+#[test]
+fn client_connects_and_gets_a_cell() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("alice");
+    harness.connect(client);
+
+    let client_id = harness.client_id(client);
+    let server_entity = harness.server_player(client_id).unwrap();
+    let lobby = harness.clients[client].world.resource::<ClientLobby>();
+    assert_eq!(lobby.players[&client_id].server_entity, server_entity);
+
+    let controlled = harness.controlled_player(client).unwrap();
+    let mapping = harness.clients[client].world.resource::<NetworkMapping>();
+    assert_eq!(mapping.0.get(&server_entity), Some(&controlled));
+}
+
+#[test]
+fn food_spawns_are_replicated() {
+    let mut harness = Harness::new();
+    harness.spawn_food();
+    let client = harness.add_client("alice");
+    harness.connect(client);
+
+    let replicated = harness.step_until(120, |harness| {
+        count::<With<NpcCell>>(&mut harness.clients[client]) >= 3
+    });
+    assert!(replicated, "client never saw the spawned food");
+
+    // reliable messages may still be in flight, let them settle before comparing
+    let settled = harness.step_until(60, |harness| {
+        count::<With<NpcCell>>(&mut harness.server)
+            == count::<With<NpcCell>>(&mut harness.clients[client])
+    });
+    assert!(settled);
+}
+
+#[test]
+fn player_eats_food() {
+    let mut harness = Harness::new();
+    let client = harness.add_client("alice");
+    harness.connect(client);
+
+    let client_id = harness.client_id(client);
+    let player_entity = harness.server_player(client_id).unwrap();
+    let translation = harness
+        .server
+        .world
+        .get::<Transform>(player_entity)
+        .unwrap()
+        .translation;
+
+    let food_size = 0.5;
+    let food = harness
+        .server
+        .world
+        .spawn((
+            TransformBundle::from_transform(Transform::from_translation(translation)),
+            Cell { size: food_size },
+            NpcCell,
+            Collider::ball(food_size / 2.0),
+            PhysicsBundle::moving_entity(),
+        ))
+        .id();
+
+    let eaten = harness.step_until(60, |harness| {
+        harness.server.world.get_entity(food).is_none()
+    });
+    assert!(eaten, "food was never eaten");
+
+    let size = harness
+        .server
+        .world
+        .get::<Cell>(player_entity)
+        .unwrap()
+        .size;
+    assert_eq!(size, INITIAL_PLAYER_SIZE + food_size / 2.0);
+
+    let grew = harness.step_until(60, |harness| {
+        let controlled = harness.controlled_player(client).unwrap();
+        harness.clients[client]
+            .world
+            .get::<Cell>(controlled)
+            .unwrap()
+            .size
+            > INITIAL_PLAYER_SIZE
+    });
+    assert!(grew, "client never saw its cell grow");
+}
+
+#[test]
+fn disconnect_removes_player_everywhere() {
+    let mut harness = Harness::new();
+    let leaving = harness.add_client("alice");
+    let staying = harness.add_client("bob");
+    harness.connect(leaving);
+    harness.connect(staying);
+
+    let leaving_id = harness.client_id(leaving);
+    let known = harness.step_until(60, |harness| {
+        harness.clients[staying]
+            .world
+            .resource::<ClientLobby>()
+            .players
+            .contains_key(&leaving_id)
+    });
+    assert!(known, "other client never saw the player join");
+
+    harness.clients[leaving]
+        .world
+        .resource_mut::<bevy_renet::renet::RenetClient>()
+        .disconnect();
+
+    let removed = harness.step_until(1200, |harness| {
+        harness.server_player(leaving_id).is_none()
+            && !harness.clients[staying]
+                .world
+                .resource::<ClientLobby>()
+                .players
+                .contains_key(&leaving_id)
+    });
+    assert!(removed, "player was not removed after disconnecting");
+}
+
+#[test]
+fn same_seed_spawns_players_at_the_same_position() {
+    let spawn_position = |seed: u64| {
+        let mut harness = Harness::with_seed(seed);
+        let client = harness.add_client("alice");
+        harness.connect(client);
+        let player_entity = harness.server_player(harness.client_id(client)).unwrap();
+        harness
+            .server
+            .world
+            .get::<Transform>(player_entity)
+            .unwrap()
+            .translation
+    };
+
+    assert_eq!(spawn_position(42), spawn_position(42));
+    assert_ne!(spawn_position(42), spawn_position(43));
+}
+
+#[test]
+fn same_seed_places_food_at_the_same_positions() {
+    let placement = SpawnPlacement::default();
+    let food_positions = |seed: u64| {
+        let mut rng = ServerRng::new(seed);
+        let mut occupied = Vec::new();
+        for _ in 0..20 {
+            let position = placement.food_spawn_point(&mut rng, &occupied).unwrap();
+            occupied.push((position, 1.0));
+        }
+        occupied
+    };
+
+    assert_eq!(food_positions(42), food_positions(42));
+    assert_ne!(food_positions(42), food_positions(43));
+}
+
+#[test]
+fn oversized_connection_data_is_capped_to_fit() {
+    // four bytes per char, the worst case for the netcode user data
+    let connection_data = ConnectionData {
+        name: "\u{1F600}".repeat(200),
+        spectator: true,
+        room: "\u{1F600}".repeat(200),
+        profile: u64::MAX,
+        session: u64::MAX,
+    };
+    let decoded = ConnectionData::from_user_data(&connection_data.to_user_data());
+
+    assert_eq!(decoded.name.chars().count(), MAX_PLAYER_NAME_LENGTH);
+    assert_eq!(decoded.room.chars().count(), MAX_ROOM_CODE_LENGTH);
+    assert!(decoded.spectator);
+    assert_eq!(decoded.profile, u64::MAX);
+    assert_eq!(decoded.session, u64::MAX);
+}